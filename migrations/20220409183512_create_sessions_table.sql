@@ -0,0 +1,6 @@
+-- Server-side session state, keyed by the value stored in the session cookie
+CREATE TABLE sessions(
+    session_key TEXT PRIMARY KEY,
+    state JSONB NOT NULL,
+    expires_at timestamptz NOT NULL
+);
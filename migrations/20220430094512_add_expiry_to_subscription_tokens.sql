@@ -0,0 +1,4 @@
+-- Tokens are valid for a limited time and can only confirm a subscriber once.
+ALTER TABLE subscription_tokens
+    ADD COLUMN created_at timestamptz NOT NULL DEFAULT now(),
+    ADD COLUMN consumed_at timestamptz NULL;
@@ -0,0 +1,32 @@
+-- Every deployment can run several newsletters.
+CREATE TABLE lists(
+    list_id uuid NOT NULL,
+    PRIMARY KEY (list_id),
+    slug TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT now()
+);
+
+-- Subscribers confirm (and leave) each list separately.
+CREATE TABLE list_memberships(
+    subscriber_id uuid NOT NULL
+        REFERENCES subscriptions (id),
+    list_id uuid NOT NULL
+        REFERENCES lists (list_id),
+    status TEXT NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (subscriber_id, list_id)
+);
+
+-- The single list everybody was on so far.
+INSERT INTO lists (list_id, slug, name)
+VALUES ('b0b7a5b4-3f7e-4c55-9f43-5a0e2c6e8d10', 'newsletter', 'Newsletter');
+
+INSERT INTO list_memberships (subscriber_id, list_id, status)
+SELECT id, 'b0b7a5b4-3f7e-4c55-9f43-5a0e2c6e8d10', status
+FROM subscriptions;
+
+ALTER TABLE newsletter_issues
+    ADD COLUMN list_id uuid REFERENCES lists (list_id);
+UPDATE newsletter_issues SET list_id = 'b0b7a5b4-3f7e-4c55-9f43-5a0e2c6e8d10';
+ALTER TABLE newsletter_issues ALTER COLUMN list_id SET NOT NULL;
@@ -0,0 +1,2 @@
+ALTER TABLE subscriptions
+    ADD COLUMN digest_frequency TEXT NOT NULL DEFAULT 'immediate';
@@ -0,0 +1,10 @@
+-- One row per delivery the worker finished with, successful or not.
+CREATE TABLE issue_delivery_log(
+    newsletter_issue_id uuid NOT NULL
+        REFERENCES newsletter_issues (newsletter_issue_id),
+    subscriber_id uuid NOT NULL
+        REFERENCES subscriptions (id),
+    outcome TEXT NOT NULL,
+    n_retries SMALLINT NOT NULL,
+    recorded_at timestamptz NOT NULL DEFAULT now()
+);
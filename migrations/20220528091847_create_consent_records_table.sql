@@ -0,0 +1,13 @@
+-- Proof of when and how each subscriber opted in.
+CREATE TABLE consent_records(
+    consent_record_id uuid NOT NULL,
+    PRIMARY KEY (consent_record_id),
+    subscriber_id uuid NOT NULL
+        REFERENCES subscriptions (id),
+    event TEXT NOT NULL,
+    recorded_at timestamptz NOT NULL,
+    ip_address TEXT NULL,
+    user_agent TEXT NULL,
+    source TEXT NOT NULL,
+    privacy_policy_version TEXT NOT NULL
+);
@@ -0,0 +1,2 @@
+-- The language we write to the subscriber in.
+ALTER TABLE subscriptions ADD COLUMN locale TEXT NOT NULL DEFAULT 'en';
@@ -0,0 +1,8 @@
+-- One fixed-window counter per rate-limited key, shared by every instance.
+CREATE TABLE rate_limit_counters(
+    key TEXT NOT NULL,
+    window_start timestamptz NOT NULL,
+    expires_at timestamptz NOT NULL,
+    hits INTEGER NOT NULL,
+    PRIMARY KEY (key)
+);
@@ -0,0 +1,9 @@
+-- A token confirms the lists it was sent for, and no others: anybody can
+-- sign up an address to more lists while its owner has a link pending.
+ALTER TABLE subscription_tokens ADD COLUMN list_ids uuid[];
+-- Tokens issued so far confirmed every list the subscriber had joined.
+UPDATE subscription_tokens t
+SET list_ids = ARRAY(
+    SELECT m.list_id FROM list_memberships m WHERE m.subscriber_id = t.subscriber_id
+);
+ALTER TABLE subscription_tokens ALTER COLUMN list_ids SET NOT NULL;
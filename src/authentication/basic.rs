@@ -0,0 +1,115 @@
+use crate::authentication::{validate_credentials, AuthError, Credentials};
+use actix_web::dev::Payload;
+use actix_web::http::header::HeaderMap;
+use actix_web::{web, FromRequest, HttpRequest};
+use anyhow::Context;
+use secrecy::Secret;
+use sqlx::PgPool;
+use std::future::Future;
+use std::pin::Pin;
+use uuid::Uuid;
+
+/// An operator that presented valid HTTP Basic credentials.
+///
+/// Add it as an argument to a request handler to reject unauthenticated
+/// callers with a `401 Unauthorized` before the handler runs.
+#[derive(Debug, Clone, Copy)]
+pub struct BasicAuthUser(Uuid);
+
+impl BasicAuthUser {
+    pub fn user_id(&self) -> Uuid {
+        self.0
+    }
+}
+
+impl FromRequest for BasicAuthUser {
+    type Error = AuthError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let credentials = basic_authentication(req.headers());
+        let pool = req.app_data::<web::Data<PgPool>>().cloned();
+        Box::pin(async move {
+            let credentials = credentials.map_err(AuthError::InvalidCredentials)?;
+            let pool = pool.context("The database pool is not registered as application data.")?;
+            let user_id = validate_credentials(credentials, &pool).await?;
+            Ok(Self(user_id))
+        })
+    }
+}
+
+/// Extract the username and password from an `Authorization: Basic` header.
+pub fn basic_authentication(headers: &HeaderMap) -> Result<Credentials, anyhow::Error> {
+    let header_value = headers
+        .get("Authorization")
+        .context("The 'Authorization' header was missing")?
+        .to_str()
+        .context("The 'Authorization' header was not a valid UTF8 string.")?;
+    let base64encoded_segment = header_value
+        .strip_prefix("Basic ")
+        .context("The authorization scheme was not 'Basic'.")?;
+    let decoded_bytes = base64::decode_config(base64encoded_segment, base64::STANDARD)
+        .context("Failed to base64-decode 'Basic' credentials.")?;
+    let decoded_credentials = String::from_utf8(decoded_bytes)
+        .context("The decoded credential string is not valid UTF8.")?;
+
+    // Split into two segments, using ':' as delimitator
+    let mut credentials = decoded_credentials.splitn(2, ':');
+    let username = credentials
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("A username must be provided in 'Basic' auth."))?
+        .to_string();
+    let password = credentials
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("A password must be provided in 'Basic' auth."))?
+        .to_string();
+
+    Ok(Credentials {
+        username,
+        password: Secret::new(password),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::basic_authentication;
+    use actix_web::http::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+    use claim::{assert_err, assert_ok};
+    use secrecy::ExposeSecret;
+
+    fn headers(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn valid_basic_credentials_are_parsed() {
+        let encoded = base64::encode("ursula:le:guin");
+        let credentials = assert_ok!(basic_authentication(&headers(&format!(
+            "Basic {}",
+            encoded
+        ))));
+        assert_eq!(credentials.username, "ursula");
+        assert_eq!(credentials.password.expose_secret(), "le:guin");
+    }
+
+    #[test]
+    fn a_missing_header_is_rejected() {
+        assert_err!(basic_authentication(&HeaderMap::new()));
+    }
+
+    #[test]
+    fn a_non_basic_scheme_is_rejected() {
+        assert_err!(basic_authentication(&headers("Bearer some-token")));
+    }
+
+    #[test]
+    fn credentials_without_a_password_are_rejected() {
+        let encoded = base64::encode("ursula");
+        assert_err!(basic_authentication(&headers(&format!(
+            "Basic {}",
+            encoded
+        ))));
+    }
+}
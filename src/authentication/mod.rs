@@ -0,0 +1,9 @@
+mod basic;
+mod middleware;
+mod password;
+
+pub use basic::{basic_authentication, BasicAuthUser};
+pub use middleware::{reject_anonymous_users, UserId};
+pub use password::{
+    compute_password_hash, seed_admin, validate_credentials, AuthError, Credentials,
+};
@@ -0,0 +1,256 @@
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use rand::{thread_rng, Rng};
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+
+/// What the bot checks made of a sign-up, recorded on its span.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BotCheck {
+    Passed,
+    /// The hidden field humans never see was filled in.
+    Honeypot,
+    MissingChallenge,
+    /// The challenge was not issued by us.
+    InvalidChallenge,
+    ExpiredChallenge,
+    /// The nonce does not solve the challenge.
+    InsufficientWork,
+}
+
+impl BotCheck {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BotCheck::Passed => "passed",
+            BotCheck::Honeypot => "honeypot",
+            BotCheck::MissingChallenge => "missing_challenge",
+            BotCheck::InvalidChallenge => "invalid_challenge",
+            BotCheck::ExpiredChallenge => "expired_challenge",
+            BotCheck::InsufficientWork => "insufficient_work",
+        }
+    }
+}
+
+/// A proof-of-work puzzle for the sign-up form.
+#[derive(Debug, serde::Serialize, utoipa::ToSchema)]
+pub struct Challenge {
+    pub challenge: String,
+    /// How many leading zero bits the solution's hash needs.
+    pub difficulty: u8,
+}
+
+/// Honeypot and proof-of-work checks on sign-ups.
+///
+/// Challenges are stateless: `<issued at>.<random>.<HMAC tag>`. A solution
+/// is a `nonce` such that `SHA-256("<challenge>:<email>:<nonce>")` starts
+/// with `difficulty` zero bits; binding it to the email address means one
+/// solved challenge cannot be replayed to sign up other addresses.
+pub struct BotProtection {
+    hmac_secret: Secret<String>,
+    proof_of_work_required: bool,
+    difficulty: u8,
+    challenge_ttl: chrono::Duration,
+}
+
+impl BotProtection {
+    pub fn new(
+        hmac_secret: Secret<String>,
+        proof_of_work_required: bool,
+        difficulty: u8,
+        challenge_ttl: chrono::Duration,
+    ) -> Self {
+        Self {
+            hmac_secret,
+            proof_of_work_required,
+            difficulty,
+            challenge_ttl,
+        }
+    }
+
+    pub fn issue_challenge(&self) -> Challenge {
+        let payload = format!(
+            "{}.{}",
+            Utc::now().timestamp(),
+            hex::encode(thread_rng().gen::<[u8; 16]>())
+        );
+        let tag = hex::encode(self.mac(&payload).finalize().into_bytes());
+        Challenge {
+            challenge: format!("{}.{}", payload, tag),
+            difficulty: self.difficulty,
+        }
+    }
+
+    /// Proof of work is only asked for when `proof_of_work_required` is set;
+    /// the honeypot is always checked.
+    pub fn check(
+        &self,
+        honeypot: Option<&str>,
+        challenge: Option<&str>,
+        nonce: Option<&str>,
+        email: &str,
+    ) -> BotCheck {
+        if honeypot.is_some_and(|h| !h.is_empty()) {
+            return BotCheck::Honeypot;
+        }
+        if !self.proof_of_work_required {
+            return BotCheck::Passed;
+        }
+        let (challenge, nonce) = match (challenge, nonce) {
+            (Some(challenge), Some(nonce)) => (challenge, nonce),
+            _ => return BotCheck::MissingChallenge,
+        };
+        let issued_at = match self.verify_challenge(challenge) {
+            Some(issued_at) => issued_at,
+            None => return BotCheck::InvalidChallenge,
+        };
+        if issued_at + self.challenge_ttl.num_seconds() < Utc::now().timestamp() {
+            return BotCheck::ExpiredChallenge;
+        }
+        if leading_zero_bits(&solution_hash(challenge, email, nonce)) < u32::from(self.difficulty) {
+            return BotCheck::InsufficientWork;
+        }
+        BotCheck::Passed
+    }
+
+    /// The time the challenge was issued at, if we issued it.
+    fn verify_challenge(&self, challenge: &str) -> Option<i64> {
+        let (payload, tag) = challenge.rsplit_once('.')?;
+        let tag = hex::decode(tag).ok()?;
+        self.mac(payload).verify_slice(&tag).ok()?;
+        payload.split('.').next()?.parse().ok()
+    }
+
+    fn mac(&self, payload: &str) -> Hmac<Sha256> {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(self.hmac_secret.expose_secret().as_bytes()).unwrap();
+        mac.update(b"sign-up-challenge:");
+        mac.update(payload.as_bytes());
+        mac
+    }
+}
+
+fn solution_hash(challenge: &str, email: &str, nonce: &str) -> [u8; 32] {
+    Sha256::digest(format!("{}:{}:{}", challenge, email, nonce).as_bytes()).into()
+}
+
+fn leading_zero_bits(hash: &[u8]) -> u32 {
+    let mut bits = 0;
+    for byte in hash {
+        bits += byte.leading_zeros();
+        if *byte != 0 {
+            break;
+        }
+    }
+    bits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{leading_zero_bits, solution_hash, BotCheck, BotProtection};
+    use secrecy::Secret;
+
+    const EMAIL: &str = "ursula@example.com";
+
+    fn bot_protection(ttl_seconds: i64) -> BotProtection {
+        BotProtection::new(
+            Secret::new("secret".into()),
+            true,
+            8,
+            chrono::Duration::seconds(ttl_seconds),
+        )
+    }
+
+    fn solves(challenge: &str, email: &str, nonce: &str, difficulty: u8) -> bool {
+        leading_zero_bits(&solution_hash(challenge, email, nonce)) >= u32::from(difficulty)
+    }
+
+    fn solve(challenge: &str, email: &str, difficulty: u8) -> String {
+        (0u64..)
+            .map(|n| n.to_string())
+            .find(|nonce| solves(challenge, email, nonce, difficulty))
+            .unwrap()
+    }
+
+    #[test]
+    fn leading_zero_bits_are_counted_across_bytes() {
+        assert_eq!(leading_zero_bits(&[0, 0, 0b0001_0000, 0xff]), 19);
+        assert_eq!(leading_zero_bits(&[0xff]), 0);
+    }
+
+    #[test]
+    fn a_solved_challenge_passes() {
+        let protection = bot_protection(600);
+        let challenge = protection.issue_challenge();
+        let nonce = solve(&challenge.challenge, EMAIL, challenge.difficulty);
+
+        let check = protection.check(None, Some(&challenge.challenge), Some(&nonce), EMAIL);
+
+        assert_eq!(check, BotCheck::Passed);
+    }
+
+    #[test]
+    fn a_solution_cannot_be_reused_for_another_address() {
+        let protection = bot_protection(600);
+        let other_email = "someone_else@example.com";
+        let challenge = protection.issue_challenge();
+        let nonce = (0u64..)
+            .map(|n| n.to_string())
+            .find(|nonce| {
+                solves(&challenge.challenge, EMAIL, nonce, challenge.difficulty)
+                    && !solves(
+                        &challenge.challenge,
+                        other_email,
+                        nonce,
+                        challenge.difficulty,
+                    )
+            })
+            .unwrap();
+
+        let check = protection.check(None, Some(&challenge.challenge), Some(&nonce), other_email);
+
+        assert_eq!(check, BotCheck::InsufficientWork);
+    }
+
+    #[test]
+    fn filled_in_honeypots_are_caught_even_without_proof_of_work() {
+        let protection = BotProtection::new(
+            Secret::new("secret".into()),
+            false,
+            8,
+            chrono::Duration::seconds(600),
+        );
+
+        assert_eq!(
+            protection.check(Some("http://spam.example"), None, None, EMAIL),
+            BotCheck::Honeypot
+        );
+        assert_eq!(
+            protection.check(Some(""), None, None, EMAIL),
+            BotCheck::Passed
+        );
+    }
+
+    #[test]
+    fn challenges_must_be_present_signed_and_fresh() {
+        let protection = bot_protection(600);
+        assert_eq!(
+            protection.check(None, None, None, EMAIL),
+            BotCheck::MissingChallenge
+        );
+
+        let forged = "1654956000.00000000000000000000000000000000.deadbeef";
+        let nonce = solve(forged, EMAIL, 8);
+        assert_eq!(
+            protection.check(None, Some(forged), Some(&nonce), EMAIL),
+            BotCheck::InvalidChallenge
+        );
+
+        let expired = bot_protection(-1);
+        let challenge = expired.issue_challenge();
+        let nonce = solve(&challenge.challenge, EMAIL, challenge.difficulty);
+        assert_eq!(
+            expired.check(None, Some(&challenge.challenge), Some(&nonce), EMAIL),
+            BotCheck::ExpiredChallenge
+        );
+    }
+}
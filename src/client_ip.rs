@@ -0,0 +1,99 @@
+use actix_web::HttpRequest;
+use std::net::IpAddr;
+
+/// The reverse proxies in front of the application, whose
+/// `X-Forwarded-For` header we believe.
+#[derive(Debug, Clone)]
+pub struct TrustedProxies(pub Vec<IpAddr>);
+
+impl TrustedProxies {
+    /// The address of the client, looking through trusted reverse proxies.
+    ///
+    /// `X-Forwarded-For` is only believed when the request comes from one of
+    /// our proxies; it is then read right to left up to the first hop that
+    /// is not one of ours, since anything further left is client-supplied.
+    pub fn client_ip(&self, request: &HttpRequest) -> Option<IpAddr> {
+        let trusted_proxies = &self.0;
+        let mut client = request.peer_addr()?.ip();
+        if !trusted_proxies.contains(&client) {
+            return Some(client);
+        }
+        let forwarded_for: Vec<&str> = request
+            .headers()
+            .get_all("X-Forwarded-For")
+            .filter_map(|h| h.to_str().ok())
+            .flat_map(|h| h.split(','))
+            .map(str::trim)
+            .collect();
+        for hop in forwarded_for.into_iter().rev() {
+            match hop.parse() {
+                Ok(ip) => client = ip,
+                Err(_) => break,
+            }
+            if !trusted_proxies.contains(&client) {
+                break;
+            }
+        }
+        Some(client)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TrustedProxies;
+    use actix_web::test::TestRequest;
+    use std::net::IpAddr;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn the_peer_address_is_used_when_it_is_not_a_trusted_proxy() {
+        let request = TestRequest::default()
+            .peer_addr("203.0.113.7:4242".parse().unwrap())
+            .insert_header(("X-Forwarded-For", "198.51.100.1"))
+            .to_http_request();
+        assert_eq!(
+            TrustedProxies(vec![ip("10.0.0.1")]).client_ip(&request),
+            Some(ip("203.0.113.7"))
+        );
+    }
+
+    #[test]
+    fn the_forwarded_address_is_used_behind_a_trusted_proxy() {
+        let request = TestRequest::default()
+            .peer_addr("10.0.0.1:4242".parse().unwrap())
+            .insert_header(("X-Forwarded-For", "198.51.100.1"))
+            .to_http_request();
+        assert_eq!(
+            TrustedProxies(vec![ip("10.0.0.1")]).client_ip(&request),
+            Some(ip("198.51.100.1"))
+        );
+    }
+
+    #[test]
+    fn client_supplied_hops_are_ignored() {
+        let request = TestRequest::default()
+            .peer_addr("10.0.0.1:4242".parse().unwrap())
+            .insert_header(("X-Forwarded-For", "1.2.3.4, 198.51.100.1, 10.0.0.2"))
+            .to_http_request();
+        let trusted_proxies = TrustedProxies(vec![ip("10.0.0.1"), ip("10.0.0.2")]);
+        assert_eq!(
+            trusted_proxies.client_ip(&request),
+            Some(ip("198.51.100.1"))
+        );
+    }
+
+    #[test]
+    fn a_malformed_hop_stops_the_walk() {
+        let request = TestRequest::default()
+            .peer_addr("10.0.0.1:4242".parse().unwrap())
+            .insert_header(("X-Forwarded-For", "198.51.100.1, not-an-ip"))
+            .to_http_request();
+        assert_eq!(
+            TrustedProxies(vec![ip("10.0.0.1")]).client_ip(&request),
+            Some(ip("10.0.0.1"))
+        );
+    }
+}
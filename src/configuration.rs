@@ -1,15 +1,261 @@
-use crate::domain::SubscriberEmail;
+use crate::bot_protection::BotProtection;
+use crate::domain::{Locale, SubscriberEmail};
+use crate::email_client::{
+    EmailClient, FileEmailClient, MeteredEmailClient, PostmarkEmailClient, RetryPolicy,
+    RetryingEmailClient, SmtpEmailClient, SmtpTls,
+};
+use crate::health::ReadinessProbe;
+use crate::metrics::Metrics;
+use crate::rate_limit::{
+    InMemoryRateLimitStore, Limit, PostgresRateLimitStore, RateLimitStore, RateLimiter,
+};
+use crate::shutdown::Shutdown;
+use crate::telemetry::otlp_tracer;
+use anyhow::Context;
+use argon2::PasswordHash;
 use core::convert::{TryFrom, TryInto};
+use opentelemetry::trace::TraceError;
+use opentelemetry_sdk::trace::Tracer;
 use secrecy::{ExposeSecret, Secret};
-use serde_aux::field_attributes::deserialize_number_from_string;
+use serde_aux::field_attributes::{
+    deserialize_number_from_string, deserialize_option_number_from_string,
+};
 use sqlx::postgres::{PgConnectOptions, PgSslMode};
-use sqlx::ConnectOptions;
+use sqlx::{ConnectOptions, PgPool};
+use std::net::IpAddr;
+use std::sync::Arc;
+
+/// The `application.hmac_secret` committed in `base.yaml`: anyone can read
+/// it, so it must be overridden in production.
+const COMMITTED_HMAC_SECRET: &str =
+    "super-long-and-secret-random-key-needed-to-verify-message-integrity";
+
+/// Beyond this many leading zero bits, browsers would take minutes to
+/// solve a challenge.
+const MAX_DIFFICULTY_BITS: u8 = 32;
 
 #[derive(serde::Deserialize, Debug, Clone)]
 pub struct Settings {
     pub database: DatabaseSettings,
     pub application: ApplicationSettings,
+    pub admin: AdminSettings,
     pub email_client: EmailClientSettings,
+    pub subscription_tokens: SubscriptionTokenSettings,
+    pub idempotency: IdempotencySettings,
+    pub consent: ConsentSettings,
+    pub email_templates: EmailTemplateSettings,
+    pub localization: LocalizationSettings,
+    pub rate_limits: RateLimitSettings,
+    pub bot_protection: BotProtectionSettings,
+    pub health: HealthSettings,
+    pub telemetry: TelemetrySettings,
+    /// Taken from `APP_ENVIRONMENT`.
+    pub environment: Environment,
+}
+
+impl Settings {
+    /// Check every setting up front, so that a misconfigured deployment
+    /// fails at startup with the full list of what needs fixing rather than
+    /// one problem at a time, or later at runtime.
+    pub fn validate(&self) -> Result<(), InvalidSettings> {
+        let production = self.environment == Environment::Production;
+        let mut problems = Problems::default();
+
+        let application = &self.application;
+        problems.url("application.base_url", &application.base_url, production);
+        if application.hmac_secret.expose_secret().len() < 64 {
+            problems.add("application.hmac_secret", "must be at least 64 bytes long.");
+        } else if production && application.hmac_secret.expose_secret() == COMMITTED_HMAC_SECRET {
+            problems.add(
+                "application.hmac_secret",
+                "must not be the example from `base.yaml` in production.",
+            );
+        }
+        if application.port != 0 && application.admin_port == Some(application.port) {
+            problems.add(
+                "application.admin_port",
+                "must differ from `application.port`.",
+            );
+        }
+        problems.positive(
+            "application.shutdown_grace_period_seconds",
+            application.shutdown_grace_period_seconds,
+        );
+
+        if self.admin.username.trim().is_empty() {
+            problems.add("admin.username", "must not be empty.");
+        }
+        if let Some(password_hash) = &self.admin.password_hash {
+            if let Err(e) = PasswordHash::new(password_hash.expose_secret()) {
+                problems.add(
+                    "admin.password_hash",
+                    format!("is not a password hash in PHC string format: {}.", e),
+                );
+            }
+        }
+
+        problems.positive("database.port", self.database.port);
+        if production && !self.database.require_ssl {
+            problems.add("database.require_ssl", "must be enabled in production.");
+        }
+
+        let email_client = &self.email_client;
+        if let Err(e) = email_client.sender() {
+            problems.add("email_client.sender_email", e);
+        }
+        problems.positive(
+            "email_client.timeout_milliseconds",
+            email_client.timeout_milliseconds,
+        );
+        problems.positive(
+            "email_client.retry.max_attempts",
+            email_client.retry.max_attempts,
+        );
+        if email_client.retry.max_delay_milliseconds < email_client.retry.base_delay_milliseconds {
+            problems.add(
+                "email_client.retry.max_delay_milliseconds",
+                "must not be less than `base_delay_milliseconds`.",
+            );
+        }
+        match email_client.transport {
+            EmailTransport::Postmark => {
+                problems.url("email_client.base_url", &email_client.base_url, production)
+            }
+            EmailTransport::Smtp => match &email_client.smtp {
+                Some(smtp) => {
+                    if smtp.host.trim().is_empty() {
+                        problems.add("email_client.smtp.host", "must not be empty.");
+                    }
+                    problems.positive("email_client.smtp.port", smtp.port);
+                    if production && smtp.tls == SmtpTls::None {
+                        problems.add("email_client.smtp.tls", "must not be `none` in production.");
+                    }
+                }
+                None => problems.add("email_client.smtp", "is required by the `smtp` transport."),
+            },
+            EmailTransport::File => {
+                if email_client.file.is_none() {
+                    problems.add("email_client.file", "is required by the `file` transport.");
+                }
+            }
+        }
+
+        problems.positive(
+            "subscription_tokens.ttl_hours",
+            self.subscription_tokens.ttl_hours,
+        );
+        problems.positive(
+            "subscription_tokens.cleanup_interval_seconds",
+            self.subscription_tokens.cleanup_interval_seconds,
+        );
+        problems.positive("idempotency.ttl_hours", self.idempotency.ttl_hours);
+        let rate_limits = &self.rate_limits;
+        problems.positive(
+            "rate_limits.per_ip.max_requests",
+            rate_limits.per_ip.max_requests,
+        );
+        problems.positive(
+            "rate_limits.per_ip.window_seconds",
+            rate_limits.per_ip.window_seconds,
+        );
+        problems.positive(
+            "rate_limits.per_email.max_requests",
+            rate_limits.per_email.max_requests,
+        );
+        problems.positive(
+            "rate_limits.per_email.window_seconds",
+            rate_limits.per_email.window_seconds,
+        );
+        if !(1..=MAX_DIFFICULTY_BITS).contains(&self.bot_protection.difficulty_bits) {
+            problems.add(
+                "bot_protection.difficulty_bits",
+                format!("must be between 1 and {}.", MAX_DIFFICULTY_BITS),
+            );
+        }
+        problems.positive(
+            "bot_protection.challenge_ttl_seconds",
+            self.bot_protection.challenge_ttl_seconds,
+        );
+        problems.positive(
+            "health.timeout_milliseconds",
+            self.health.timeout_milliseconds,
+        );
+        problems.positive(
+            "telemetry.export_timeout_milliseconds",
+            self.telemetry.export_timeout_milliseconds,
+        );
+        if let Some(endpoint) = &self.telemetry.otlp_endpoint {
+            problems.url("telemetry.otlp_endpoint", endpoint, false);
+        }
+
+        problems.into_result()
+    }
+}
+
+/// A setting that cannot be used as it is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SettingsProblem {
+    /// Where it lives in the configuration files, e.g. `database.port`.
+    pub key: &'static str,
+    pub message: String,
+}
+
+/// Everything `Settings::validate` found wrong.
+#[derive(Debug)]
+pub struct InvalidSettings(pub Vec<SettingsProblem>);
+
+impl std::fmt::Display for InvalidSettings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The configuration is invalid:")?;
+        for problem in &self.0 {
+            write!(f, "\n  - {}: {}", problem.key, problem.message)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for InvalidSettings {}
+
+#[derive(Default)]
+struct Problems(Vec<SettingsProblem>);
+
+impl Problems {
+    fn add(&mut self, key: &'static str, message: impl Into<String>) {
+        self.0.push(SettingsProblem {
+            key,
+            message: message.into(),
+        });
+    }
+
+    /// Durations, ports and counts where 0 (or less) makes no sense.
+    fn positive(&mut self, key: &'static str, value: impl Into<i128>) {
+        if value.into() <= 0 {
+            self.add(key, "must be greater than 0.");
+        }
+    }
+
+    /// An absolute `http` or `https` URL; `https` only if `require_tls`.
+    fn url(&mut self, key: &'static str, url: &str, require_tls: bool) {
+        let parsed = match reqwest::Url::parse(url) {
+            Ok(parsed) => parsed,
+            Err(e) => return self.add(key, format!("`{}` is not an absolute URL: {}.", url, e)),
+        };
+        if !["http", "https"].contains(&parsed.scheme()) {
+            self.add(key, format!("`{}` is not an http(s) URL.", url));
+        } else if !parsed.has_host() {
+            self.add(key, format!("`{}` has no host.", url));
+        } else if require_tls && parsed.scheme() != "https" {
+            self.add(key, format!("`{}` must use https in production.", url));
+        }
+    }
+
+    fn into_result(self) -> Result<(), InvalidSettings> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(InvalidSettings(self.0))
+        }
+    }
 }
 
 #[derive(serde::Deserialize, Debug, Clone)]
@@ -18,6 +264,47 @@ pub struct ApplicationSettings {
     pub port: u16,
     pub host: String,
     pub base_url: String,
+    /// Key used to sign session and flash message cookies.
+    /// It must be at least 64 bytes long.
+    pub hmac_secret: Secret<String>,
+    /// Reverse proxies whose `X-Forwarded-For` header we believe when
+    /// working out the address of a client.
+    pub trusted_proxies: Vec<IpAddr>,
+    /// Serve `/metrics` on this port, on the same host, instead of the
+    /// main one: keep it out of reach of the public.
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub admin_port: Option<u16>,
+    /// Serve browsable API documentation at `/api-docs`. `/openapi.json`
+    /// is always served.
+    pub api_docs_page: bool,
+    /// How long in-flight requests and background work are given to finish
+    /// once we are asked to shut down.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub shutdown_grace_period_seconds: u64,
+    /// How long readiness checks fail, while connections are still
+    /// accepted, before we stop listening: load balancers need that long to
+    /// stop sending us traffic.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub shutdown_pre_stop_delay_seconds: u64,
+}
+
+impl ApplicationSettings {
+    pub fn shutdown_grace_period(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.shutdown_grace_period_seconds)
+    }
+
+    pub fn shutdown_pre_stop_delay(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.shutdown_pre_stop_delay_seconds)
+    }
+}
+
+/// The operator account created at startup, if it does not exist yet.
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct AdminSettings {
+    pub username: String,
+    /// Argon2 hash of the password, in PHC string format. No account is
+    /// created unless it is set, e.g. through `APP_ADMIN__PASSWORD_HASH`.
+    pub password_hash: Option<Secret<String>>,
 }
 
 #[derive(serde::Deserialize, Debug, Clone)]
@@ -33,15 +320,319 @@ pub struct DatabaseSettings {
 
 #[derive(serde::Deserialize, Debug, Clone)]
 pub struct EmailClientSettings {
+    pub transport: EmailTransport,
     pub base_url: String,
     pub sender_email: String,
     pub authorization_token: Secret<String>,
     pub timeout_milliseconds: u64,
+    /// How transient failures are retried, whatever the transport.
+    pub retry: RetrySettings,
+    /// Required when `transport` is `smtp`.
+    pub smtp: Option<SmtpSettings>,
+    /// Required when `transport` is `file`.
+    pub file: Option<FileSinkSettings>,
+}
+
+/// The backend used to deliver emails.
+#[derive(serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EmailTransport {
+    /// Postmark's HTTP API, configured by `base_url` and `authorization_token`.
+    Postmark,
+    Smtp,
+    /// Write `.eml` files to a local directory.
+    File,
+}
+
+impl EmailTransport {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EmailTransport::Postmark => "postmark",
+            EmailTransport::Smtp => "smtp",
+            EmailTransport::File => "file",
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct SmtpSettings {
+    pub host: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub port: u16,
+    pub tls: SmtpTls,
+    pub username: Option<String>,
+    pub password: Option<Secret<String>>,
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct FileSinkSettings {
+    pub directory: String,
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct RetrySettings {
+    pub max_attempts: u32,
+    pub base_delay_milliseconds: u64,
+    pub max_delay_milliseconds: u64,
+    pub jitter: bool,
+}
+
+impl RetrySettings {
+    pub fn policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: self.max_attempts,
+            base_delay: std::time::Duration::from_millis(self.base_delay_milliseconds),
+            max_delay: std::time::Duration::from_millis(self.max_delay_milliseconds),
+            jitter: self.jitter,
+        }
+    }
+}
+
+/// Lifetime of the tokens sent in confirmation emails.
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct SubscriptionTokenSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub ttl_hours: i64,
+    /// How often tokens older than `ttl_hours` are deleted.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub cleanup_interval_seconds: u64,
+}
+
+/// How long `POST /newsletters` remembers the idempotency keys it has seen.
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct IdempotencySettings {
+    /// How long the response to a request is replayed to retries with the
+    /// same idempotency key. Past that, a retry is processed as a new
+    /// request; the cleanup worker deletes the expired keys.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub ttl_hours: i64,
+}
+
+/// What goes into the consent records kept for each subscriber.
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct ConsentSettings {
+    /// Recorded alongside every opt-in.
+    pub privacy_policy_version: String,
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct EmailTemplateSettings {
+    /// Where to look for templates overriding the compiled-in ones.
+    /// It is fine for it not to exist.
+    pub directory: String,
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct LocalizationSettings {
+    /// Used when neither the sign-up form nor `Accept-Language` name a
+    /// locale we support.
+    pub default_locale: Locale,
+}
+
+/// Limits on `POST /subscriptions`, which sends an email on every call.
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct RateLimitSettings {
+    pub store: RateLimitStoreKind,
+    pub per_ip: LimitSettings,
+    pub per_email: LimitSettings,
+}
+
+#[derive(serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RateLimitStoreKind {
+    /// Shared by every instance using the same database.
+    Postgres,
+    /// Local to each instance.
+    Memory,
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct LimitSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_requests: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub window_seconds: u64,
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct BotProtectionSettings {
+    /// Whether sign-ups must solve a challenge from
+    /// `GET /subscriptions/challenge`. The honeypot is always checked.
+    pub proof_of_work_required: bool,
+    /// Leading zero bits asked of a solution: each one doubles the work.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub difficulty_bits: u8,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub challenge_ttl_seconds: i64,
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct HealthSettings {
+    /// Whether `/health/ready` also fails when the email provider cannot
+    /// be reached.
+    pub check_email_provider: bool,
+    /// For each dependency check.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub timeout_milliseconds: u64,
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct TelemetrySettings {
+    /// Name our spans are reported under.
+    pub service_name: String,
+    /// Base URL of an OpenTelemetry collector accepting OTLP over HTTP,
+    /// e.g. `http://localhost:4318`. Spans are only exported when set.
+    #[serde(default)]
+    pub otlp_endpoint: Option<String>,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub export_timeout_milliseconds: u64,
+}
+
+impl TelemetrySettings {
+    /// `None` when there is no collector to export to.
+    pub fn tracer(&self) -> Result<Option<Tracer>, TraceError> {
+        self.otlp_endpoint
+            .as_deref()
+            .map(|endpoint| {
+                otlp_tracer(
+                    endpoint,
+                    &self.service_name,
+                    std::time::Duration::from_millis(self.export_timeout_milliseconds),
+                )
+            })
+            .transpose()
+    }
+}
+
+impl HealthSettings {
+    pub fn readiness_probe(
+        &self,
+        pool: PgPool,
+        email_client: Arc<dyn EmailClient>,
+        shutdown: Shutdown,
+    ) -> ReadinessProbe {
+        ReadinessProbe::new(
+            pool,
+            Some(email_client).filter(|_| self.check_email_provider),
+            std::time::Duration::from_millis(self.timeout_milliseconds),
+            shutdown,
+        )
+    }
+}
+
+impl BotProtectionSettings {
+    pub fn bot_protection(&self, hmac_secret: Secret<String>) -> BotProtection {
+        BotProtection::new(
+            hmac_secret,
+            self.proof_of_work_required,
+            self.difficulty_bits,
+            chrono::Duration::seconds(self.challenge_ttl_seconds),
+        )
+    }
+}
+
+impl RateLimitSettings {
+    pub fn limiter(&self, pool: PgPool) -> RateLimiter {
+        let store: Arc<dyn RateLimitStore> = match self.store {
+            RateLimitStoreKind::Postgres => Arc::new(PostgresRateLimitStore::new(pool)),
+            RateLimitStoreKind::Memory => Arc::new(InMemoryRateLimitStore::default()),
+        };
+        RateLimiter::new(store, self.per_ip.limit(), self.per_email.limit())
+    }
+}
+
+impl LimitSettings {
+    pub fn limit(&self) -> Limit {
+        Limit {
+            max_requests: self.max_requests,
+            window: std::time::Duration::from_secs(self.window_seconds),
+        }
+    }
+}
+
+impl IdempotencySettings {
+    pub fn ttl(&self) -> chrono::Duration {
+        chrono::Duration::hours(self.ttl_hours)
+    }
+}
+
+impl SubscriptionTokenSettings {
+    pub fn ttl(&self) -> chrono::Duration {
+        chrono::Duration::hours(self.ttl_hours)
+    }
+
+    pub fn cleanup_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.cleanup_interval_seconds)
+    }
 }
 
 impl EmailClientSettings {
+    pub fn client(self) -> Result<Arc<dyn EmailClient>, anyhow::Error> {
+        let policy = self.retry.policy();
+        Ok(Arc::new(RetryingEmailClient::new(self.backend()?, policy)))
+    }
+
+    /// `client`, with every attempt at sending an email recorded in
+    /// `metrics`: retries are counted and timed one by one.
+    pub fn metered_client(
+        self,
+        metrics: Arc<Metrics>,
+    ) -> Result<Arc<dyn EmailClient>, anyhow::Error> {
+        let policy = self.retry.policy();
+        let label = self.transport.as_str();
+        let backend = MeteredEmailClient::new(self.backend()?, label, metrics);
+        Ok(Arc::new(RetryingEmailClient::new(
+            Arc::new(backend),
+            policy,
+        )))
+    }
+
+    /// The configured transport, without retries.
+    fn backend(self) -> Result<Arc<dyn EmailClient>, anyhow::Error> {
+        let sender_email = self
+            .sender()
+            .map_err(anyhow::Error::msg)
+            .context("Invalid sender email address.")?;
+        let timeout = self.timeout();
+        let client: Arc<dyn EmailClient> = match self.transport {
+            EmailTransport::Postmark => Arc::new(PostmarkEmailClient::new(
+                self.base_url,
+                sender_email,
+                self.authorization_token,
+                timeout,
+            )),
+            EmailTransport::Smtp => {
+                let smtp = self
+                    .smtp
+                    .context("Missing `email_client.smtp` settings for the SMTP transport.")?;
+                let credentials = smtp.username.zip(smtp.password);
+                Arc::new(
+                    SmtpEmailClient::new(
+                        &smtp.host,
+                        smtp.port,
+                        smtp.tls,
+                        credentials,
+                        sender_email,
+                        timeout,
+                    )
+                    .context("Invalid SMTP settings.")?,
+                )
+            }
+            EmailTransport::File => {
+                let file = self
+                    .file
+                    .context("Missing `email_client.file` settings for the file transport.")?;
+                Arc::new(
+                    FileEmailClient::new(file.directory, sender_email)
+                        .context("Failed to create the email output directory.")?,
+                )
+            }
+        };
+        Ok(client)
+    }
+
     pub fn sender(&self) -> Result<SubscriberEmail, String> {
-        SubscriberEmail::parse(self.sender_email.clone())
+        SubscriberEmail::parse(self.sender_email.clone()).map_err(|e| e.to_string())
     }
 
     pub fn timeout(&self) -> std::time::Duration {
@@ -59,7 +650,7 @@ impl DatabaseSettings {
         PgConnectOptions::new()
             .host(&self.host)
             .username(&self.username)
-            .password(&self.password.expose_secret())
+            .password(self.password.expose_secret())
             .port(self.port)
             .ssl_mode(ssl_mode)
     }
@@ -71,6 +662,8 @@ impl DatabaseSettings {
     }
 }
 
+#[derive(serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
 pub enum Environment {
     Local,
     Production,
@@ -113,5 +706,168 @@ pub fn get_configuration() -> Result<Settings, config::ConfigError> {
         config::File::from(configuration_directory.join(environment.as_str())).required(true),
     )?;
     settings.merge(config::Environment::with_prefix("app").separator("__"))?;
+    settings.set("environment", environment.as_str())?;
     settings.try_into()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        get_configuration, EmailTransport, Environment, Settings, SmtpSettings,
+        COMMITTED_HMAC_SECRET, MAX_DIFFICULTY_BITS,
+    };
+    use crate::email_client::SmtpTls;
+    use claim::{assert_err, assert_ok};
+    use secrecy::{ExposeSecret, Secret};
+
+    fn settings() -> Settings {
+        get_configuration().expect("Failed to read configuration.")
+    }
+
+    fn invalid_keys(settings: &Settings) -> Vec<&'static str> {
+        settings
+            .validate()
+            .unwrap_err()
+            .0
+            .into_iter()
+            .map(|p| p.key)
+            .collect()
+    }
+
+    #[test]
+    fn the_local_configuration_is_valid() {
+        assert_ok!(settings().validate());
+    }
+
+    #[test]
+    fn every_problem_is_reported_at_once() {
+        let mut settings = settings();
+        settings.email_client.sender_email = "not-an-email".into();
+        settings.application.base_url = "localhost:8000".into();
+        settings.health.timeout_milliseconds = 0;
+        settings.subscription_tokens.ttl_hours = -1;
+        settings.email_client.transport = EmailTransport::Smtp;
+        settings.email_client.smtp = None;
+
+        assert_eq!(
+            invalid_keys(&settings),
+            vec![
+                "application.base_url",
+                "email_client.sender_email",
+                "email_client.smtp",
+                "subscription_tokens.ttl_hours",
+                "health.timeout_milliseconds",
+            ]
+        );
+    }
+
+    #[test]
+    fn urls_must_be_absolute() {
+        let mut settings = settings();
+        settings.email_client.transport = EmailTransport::Postmark;
+        settings.email_client.base_url = "api.postmarkapp.com".into();
+        settings.telemetry.otlp_endpoint = Some("/v1/traces".into());
+
+        assert_eq!(
+            invalid_keys(&settings),
+            vec!["email_client.base_url", "telemetry.otlp_endpoint"]
+        );
+    }
+
+    #[test]
+    fn tls_is_required_in_production() {
+        let mut settings = settings();
+        settings.environment = Environment::Production;
+        settings.database.require_ssl = false;
+        settings.email_client.transport = EmailTransport::Smtp;
+        settings.email_client.smtp = Some(SmtpSettings {
+            host: "localhost".into(),
+            port: 25,
+            tls: SmtpTls::None,
+            username: None,
+            password: None,
+        });
+
+        assert_eq!(
+            invalid_keys(&settings),
+            vec![
+                "application.base_url",
+                "application.hmac_secret",
+                "database.require_ssl",
+                "email_client.smtp.tls",
+            ]
+        );
+
+        settings.application.base_url = "https://zero2prod.example".into();
+        settings.application.hmac_secret = Secret::new("a".repeat(64));
+        settings.database.require_ssl = true;
+        settings.email_client.smtp.as_mut().unwrap().tls = SmtpTls::StartTls;
+        assert_ok!(settings.validate());
+    }
+
+    #[test]
+    fn the_committed_hmac_secret_is_only_accepted_locally() {
+        let mut settings = settings();
+        assert_eq!(
+            settings.application.hmac_secret.expose_secret(),
+            COMMITTED_HMAC_SECRET
+        );
+        assert_ok!(settings.validate());
+
+        settings.environment = Environment::Production;
+        settings.application.base_url = "https://zero2prod.example".into();
+        settings.database.require_ssl = true;
+
+        assert_eq!(invalid_keys(&settings), vec!["application.hmac_secret"]);
+    }
+
+    #[test]
+    fn unsolvable_or_pointless_difficulties_are_rejected() {
+        for difficulty_bits in [0, MAX_DIFFICULTY_BITS + 1, u8::MAX] {
+            let mut settings = settings();
+            settings.bot_protection.difficulty_bits = difficulty_bits;
+
+            assert_eq!(
+                invalid_keys(&settings),
+                vec!["bot_protection.difficulty_bits"],
+                "difficulty_bits: {}",
+                difficulty_bits
+            );
+        }
+    }
+
+    #[test]
+    fn the_shutdown_grace_period_must_be_positive() {
+        let mut settings = settings();
+        settings.application.shutdown_grace_period_seconds = 0;
+
+        assert_eq!(
+            invalid_keys(&settings),
+            vec!["application.shutdown_grace_period_seconds"]
+        );
+    }
+
+    #[test]
+    fn the_admin_password_hash_must_be_in_phc_format() {
+        let mut settings = settings();
+        settings.admin.password_hash = Some(Secret::new("hunter2".into()));
+
+        assert_eq!(invalid_keys(&settings), vec!["admin.password_hash"]);
+    }
+
+    #[test]
+    fn the_error_lists_every_problem() {
+        let mut settings = settings();
+        settings.database.port = 0;
+        settings.application.admin_port = Some(settings.application.port);
+
+        let error = assert_err!(settings.validate()).to_string();
+
+        assert_eq!(
+            error,
+            "The configuration is invalid:\n  \
+            - application.admin_port: must differ from `application.port`.\n  \
+            - database.port: must be greater than 0."
+        );
+    }
+}
@@ -0,0 +1,81 @@
+use crate::client_ip::TrustedProxies;
+use crate::configuration::ConsentSettings;
+use actix_web::http::header::USER_AGENT;
+use actix_web::HttpRequest;
+use chrono::Utc;
+use sqlx::{Postgres, Transaction};
+use std::net::IpAddr;
+use uuid::Uuid;
+
+/// The step of the double opt-in a consent record proves.
+#[derive(Debug, Clone, Copy)]
+pub enum ConsentEvent {
+    SignUp,
+    Confirmation,
+}
+
+impl ConsentEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConsentEvent::SignUp => "sign_up",
+            ConsentEvent::Confirmation => "confirmation",
+        }
+    }
+}
+
+/// Who was on the other end of the request that gave consent.
+#[derive(Debug)]
+pub struct ConsentContext {
+    pub ip_address: Option<IpAddr>,
+    pub user_agent: Option<String>,
+}
+
+impl ConsentContext {
+    pub fn from_request(request: &HttpRequest, trusted_proxies: &TrustedProxies) -> Self {
+        Self {
+            ip_address: trusted_proxies.client_ip(request),
+            user_agent: request
+                .headers()
+                .get(USER_AGENT)
+                .and_then(|h| h.to_str().ok())
+                .map(str::to_owned),
+        }
+    }
+}
+
+#[tracing::instrument(name = "Record consent", skip(transaction, context, settings))]
+pub async fn record_consent(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    event: ConsentEvent,
+    source: &str,
+    context: &ConsentContext,
+    settings: &ConsentSettings,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO consent_records (
+            consent_record_id,
+            subscriber_id,
+            event,
+            recorded_at,
+            ip_address,
+            user_agent,
+            source,
+            privacy_policy_version
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        "#,
+        Uuid::new_v4(),
+        subscriber_id,
+        event.as_str(),
+        Utc::now(),
+        context.ip_address.map(|ip| ip.to_string()),
+        context.user_agent,
+        source,
+        settings.privacy_policy_version,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
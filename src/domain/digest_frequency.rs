@@ -0,0 +1,94 @@
+use crate::domain::InvalidInput;
+use chrono::{DateTime, Datelike, Duration, TimeZone, Utc};
+
+/// How often a subscriber wants to hear from us.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DigestFrequency {
+    Immediate,
+    Daily,
+    Weekly,
+}
+
+impl DigestFrequency {
+    pub const ALL: [DigestFrequency; 3] = [
+        DigestFrequency::Immediate,
+        DigestFrequency::Daily,
+        DigestFrequency::Weekly,
+    ];
+
+    pub fn parse(s: String) -> Result<DigestFrequency, InvalidInput> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|f| f.as_str() == s)
+            .ok_or(InvalidInput::DigestFrequency(s))
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DigestFrequency::Immediate => "immediate",
+            DigestFrequency::Daily => "daily",
+            DigestFrequency::Weekly => "weekly",
+        }
+    }
+
+    /// When an issue published at `now` is due for delivery: right away,
+    /// or at the start of the next day (resp. week, on Monday), in UTC, so
+    /// that the issues published in between go out as a single digest.
+    pub fn next_delivery(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        let days = match self {
+            DigestFrequency::Immediate => return now,
+            DigestFrequency::Daily => 1,
+            DigestFrequency::Weekly => 7 - i64::from(now.weekday().num_days_from_monday()),
+        };
+        let midnight = now.naive_utc().date().and_hms_opt(0, 0, 0).unwrap();
+        Utc.from_utc_datetime(&midnight) + Duration::days(days)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::DigestFrequency;
+    use chrono::{TimeZone, Utc};
+    use claim::{assert_err, assert_ok_eq};
+
+    #[test]
+    fn known_frequencies_are_parsed_successfully() {
+        for frequency in DigestFrequency::ALL {
+            assert_ok_eq!(DigestFrequency::parse(frequency.as_str().into()), frequency);
+        }
+    }
+
+    #[test]
+    fn unknown_frequencies_are_rejected() {
+        for frequency in &["", "hourly", "Daily"] {
+            assert_err!(DigestFrequency::parse(frequency.to_string()));
+        }
+    }
+
+    #[test]
+    fn digests_are_due_at_the_start_of_the_next_day_or_week() {
+        // A Wednesday afternoon.
+        let now = Utc.with_ymd_and_hms(2022, 6, 29, 15, 30, 0).unwrap();
+
+        assert_eq!(DigestFrequency::Immediate.next_delivery(now), now);
+        assert_eq!(
+            DigestFrequency::Daily.next_delivery(now),
+            Utc.with_ymd_and_hms(2022, 6, 30, 0, 0, 0).unwrap()
+        );
+        assert_eq!(
+            DigestFrequency::Weekly.next_delivery(now),
+            Utc.with_ymd_and_hms(2022, 7, 4, 0, 0, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn a_weekly_digest_started_on_monday_goes_out_the_next_monday() {
+        let now = Utc.with_ymd_and_hms(2022, 7, 4, 0, 0, 0).unwrap();
+
+        assert_eq!(
+            DigestFrequency::Weekly.next_delivery(now),
+            Utc.with_ymd_and_hms(2022, 7, 11, 0, 0, 0).unwrap()
+        );
+    }
+}
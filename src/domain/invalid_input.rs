@@ -0,0 +1,123 @@
+use crate::domain::Locale;
+
+/// Why a subscriber's input was rejected, worded in any supported locale.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InvalidInput {
+    BlankSubscriberName,
+    SubscriberNameTooLong,
+    /// The name, which contains one of the characters we refuse.
+    ForbiddenCharactersInName(String),
+    SubscriberEmail(String),
+    /// The slug, which is not a valid list identifier.
+    ListSlug(String),
+    /// The slugs, comma-separated, of the lists that do not exist.
+    UnknownLists(String),
+    /// The source, blank or longer than 64 characters.
+    Source(String),
+    DigestFrequency(String),
+}
+
+impl InvalidInput {
+    /// The field of the form at fault.
+    pub fn field(&self) -> &'static str {
+        match self {
+            InvalidInput::BlankSubscriberName
+            | InvalidInput::SubscriberNameTooLong
+            | InvalidInput::ForbiddenCharactersInName(_) => "name",
+            InvalidInput::SubscriberEmail(_) => "email",
+            InvalidInput::ListSlug(_) | InvalidInput::UnknownLists(_) => "lists",
+            InvalidInput::Source(_) => "source",
+            InvalidInput::DigestFrequency(_) => "digest_frequency",
+        }
+    }
+
+    /// A stable identifier of the problem, for clients to match on.
+    pub fn code(&self) -> &'static str {
+        match self {
+            InvalidInput::BlankSubscriberName => "blank",
+            InvalidInput::SubscriberNameTooLong => "too_long",
+            InvalidInput::ForbiddenCharactersInName(_) => "forbidden_characters",
+            InvalidInput::SubscriberEmail(_)
+            | InvalidInput::ListSlug(_)
+            | InvalidInput::Source(_)
+            | InvalidInput::DigestFrequency(_) => "invalid",
+            InvalidInput::UnknownLists(_) => "unknown",
+        }
+    }
+
+    pub fn localize(&self, locale: Locale) -> String {
+        match (self, locale) {
+            (InvalidInput::BlankSubscriberName, Locale::En) => {
+                "The subscriber name cannot be blank.".into()
+            }
+            (InvalidInput::BlankSubscriberName, Locale::Fr) => {
+                "Le nom d'abonné ne peut pas être vide.".into()
+            }
+            (InvalidInput::SubscriberNameTooLong, Locale::En) => {
+                "The subscriber name cannot be longer than 256 characters.".into()
+            }
+            (InvalidInput::SubscriberNameTooLong, Locale::Fr) => {
+                "Le nom d'abonné ne peut pas dépasser 256 caractères.".into()
+            }
+            (InvalidInput::ForbiddenCharactersInName(s), Locale::En) => format!(
+                r#"{} is not a valid subscriber name. It cannot contain / ( ) " < > \ {{ }}"#,
+                s
+            ),
+            (InvalidInput::ForbiddenCharactersInName(s), Locale::Fr) => format!(
+                r#"{} n'est pas un nom d'abonné valide. Il ne peut pas contenir / ( ) " < > \ {{ }}"#,
+                s
+            ),
+            (InvalidInput::SubscriberEmail(s), Locale::En) => {
+                format!("{} is not a valid subscriber email", s)
+            }
+            (InvalidInput::SubscriberEmail(s), Locale::Fr) => {
+                format!("{} n'est pas une adresse e-mail valide.", s)
+            }
+            (InvalidInput::ListSlug(s), Locale::En) => {
+                format!("{} is not a valid list identifier.", s)
+            }
+            (InvalidInput::ListSlug(s), Locale::Fr) => {
+                format!("{} n'est pas un identifiant de liste valide.", s)
+            }
+            (InvalidInput::UnknownLists(s), Locale::En) => {
+                format!("There is no list called {}.", s)
+            }
+            (InvalidInput::UnknownLists(s), Locale::Fr) => {
+                format!("Aucune liste ne s'appelle {}.", s)
+            }
+            (InvalidInput::Source(s), Locale::En) => {
+                format!("{} is not a valid source identifier.", s)
+            }
+            (InvalidInput::Source(s), Locale::Fr) => {
+                format!("{} n'est pas un identifiant de source valide.", s)
+            }
+            (InvalidInput::DigestFrequency(s), Locale::En) => {
+                format!("{} is not a valid digest frequency.", s)
+            }
+            (InvalidInput::DigestFrequency(s), Locale::Fr) => {
+                format!("{} n'est pas une fréquence de récapitulatif valide.", s)
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for InvalidInput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.localize(Locale::En))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::{InvalidInput, Locale};
+
+    #[test]
+    fn messages_are_worded_in_the_requested_locale() {
+        let error = InvalidInput::SubscriberEmail("ursula".into());
+        assert_eq!(error.to_string(), "ursula is not a valid subscriber email");
+        assert_eq!(
+            error.localize(Locale::Fr),
+            "ursula n'est pas une adresse e-mail valide."
+        );
+    }
+}
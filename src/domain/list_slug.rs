@@ -0,0 +1,73 @@
+use crate::domain::InvalidInput;
+
+/// The public identifier of a mailing list, e.g. `rust-weekly`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListSlug(String);
+
+impl ListSlug {
+    pub fn parse(s: String) -> Result<ListSlug, InvalidInput> {
+        let is_empty = s.is_empty();
+        let is_too_long = s.len() > 64;
+        let has_invalid_characters = !s
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+        if is_empty || is_too_long || has_invalid_characters {
+            Err(InvalidInput::ListSlug(s))
+        } else {
+            Ok(Self(s))
+        }
+    }
+}
+
+/// The list created alongside the `lists` table, used when a request does
+/// not name one.
+impl Default for ListSlug {
+    fn default() -> Self {
+        Self("newsletter".into())
+    }
+}
+
+impl AsRef<str> for ListSlug {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for ListSlug {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::ListSlug;
+    use claim::{assert_err, assert_ok};
+
+    #[test]
+    fn lowercase_letters_digits_and_dashes_are_valid() {
+        assert_ok!(ListSlug::parse("rust-weekly-2".to_string()));
+    }
+
+    #[test]
+    fn empty_string_is_rejected() {
+        assert_err!(ListSlug::parse("".to_string()));
+    }
+
+    #[test]
+    fn a_slug_longer_than_64_characters_is_rejected() {
+        assert_err!(ListSlug::parse("a".repeat(65)));
+    }
+
+    #[test]
+    fn slugs_with_other_characters_are_rejected() {
+        for slug in &["Rust", "rust weekly", "rust_weekly", "rüst"] {
+            assert_err!(ListSlug::parse(slug.to_string()));
+        }
+    }
+
+    #[test]
+    fn the_default_list_is_a_valid_slug() {
+        assert_ok!(ListSlug::parse(ListSlug::default().to_string()));
+    }
+}
@@ -0,0 +1,104 @@
+use std::convert::TryFrom;
+
+/// A language we can write to subscribers in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(try_from = "String")]
+pub enum Locale {
+    En,
+    Fr,
+}
+
+impl Locale {
+    pub const ALL: [Locale; 2] = [Locale::En, Locale::Fr];
+
+    /// Accepts a BCP 47 language tag, ignoring everything past the primary
+    /// language: `fr-CA` is served in French.
+    pub fn parse(s: &str) -> Result<Locale, String> {
+        let language = s.split('-').next().unwrap_or_default().trim();
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|l| l.as_str().eq_ignore_ascii_case(language))
+            .ok_or_else(|| format!("{} is not a supported locale.", s))
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Locale::En => "en",
+            Locale::Fr => "fr",
+        }
+    }
+
+    /// The supported locale the client prefers, according to the value of
+    /// its `Accept-Language` header.
+    pub fn from_accept_language(header: &str) -> Option<Locale> {
+        let mut ranges: Vec<(&str, f32)> = header
+            .split(',')
+            .filter_map(|range| {
+                let mut parts = range.split(';').map(str::trim);
+                let tag = parts.next().filter(|tag| !tag.is_empty())?;
+                let quality = parts
+                    .find_map(|p| p.strip_prefix("q="))
+                    .map_or(Some(1.0), |q| q.parse().ok())?;
+                Some((tag, quality))
+            })
+            .filter(|(_, quality)| *quality > 0.0)
+            .collect();
+        // A stable sort keeps the client's order among equal weights.
+        ranges.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        ranges
+            .into_iter()
+            .find_map(|(tag, _)| Locale::parse(tag).ok())
+    }
+}
+
+impl TryFrom<String> for Locale {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Locale::parse(&s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::Locale;
+    use claim::{assert_err, assert_ok_eq};
+
+    #[test]
+    fn supported_locales_are_parsed_successfully() {
+        for locale in Locale::ALL {
+            assert_ok_eq!(Locale::parse(locale.as_str()), locale);
+        }
+        assert_ok_eq!(Locale::parse("fr-CA"), Locale::Fr);
+        assert_ok_eq!(Locale::parse("EN-gb"), Locale::En);
+    }
+
+    #[test]
+    fn unsupported_locales_are_rejected() {
+        for locale in &["", "de", "*", "english"] {
+            assert_err!(Locale::parse(locale));
+        }
+    }
+
+    #[test]
+    fn the_preferred_supported_language_wins() {
+        let cases = [
+            ("fr-CH, fr;q=0.9, en;q=0.8", Some(Locale::Fr)),
+            ("de-DE, en;q=0.5, fr;q=0.7", Some(Locale::Fr)),
+            ("en;q=0.1, fr;q=0", Some(Locale::En)),
+            ("en, fr", Some(Locale::En)),
+            ("de, *;q=0.5", None),
+            ("", None),
+            ("fr;q=nonsense, en", Some(Locale::En)),
+        ];
+        for (header, expected) in cases.iter() {
+            assert_eq!(
+                Locale::from_accept_language(header),
+                *expected,
+                "Accept-Language: {}",
+                header
+            );
+        }
+    }
+}
@@ -1,7 +1,15 @@
+mod digest_frequency;
+mod invalid_input;
+mod list_slug;
+mod locale;
 mod new_subscriber;
 mod subscriber_email;
 mod subscriber_name;
 
+pub use digest_frequency::DigestFrequency;
+pub use invalid_input::InvalidInput;
+pub use list_slug::ListSlug;
+pub use locale::Locale;
 pub use new_subscriber::NewSubscriber;
 pub use subscriber_email::SubscriberEmail;
 pub use subscriber_name::SubscriberName;
@@ -1,6 +1,11 @@
-use crate::domain::{SubscriberEmail, SubscriberName};
+use crate::domain::{ListSlug, Locale, SubscriberEmail, SubscriberName};
 
 pub struct NewSubscriber {
     pub email: SubscriberEmail,
     pub name: SubscriberName,
+    /// The lists the subscriber asked to join. Never empty.
+    pub lists: Vec<ListSlug>,
+    pub locale: Locale,
+    /// Which form the sign-up came from, kept in the consent record.
+    pub source: String,
 }
@@ -1,18 +1,28 @@
+use crate::domain::InvalidInput;
 use unicode_segmentation::UnicodeSegmentation;
 
 #[derive(Debug)]
 pub struct SubscriberName(String);
 
 impl SubscriberName {
-    pub fn parse(s: String) -> Result<SubscriberName, String> {
-        let is_empty_or_whitespace = s.trim().is_empty();
-        let is_too_long = s.graphemes(true).count() > 256;
+    /// Every rule the name breaks is reported, e.g. both too long and
+    /// forbidden characters.
+    pub fn parse(s: String) -> Result<SubscriberName, Vec<InvalidInput>> {
         let forbidden_characters = ['/', '(', ')', '"', '<', '>', '\\', '{', '}'];
-        let contains_forbidden_characters = s.chars().any(|g| forbidden_characters.contains(&g));
-        if is_empty_or_whitespace || is_too_long || contains_forbidden_characters {
-            Err(format!("{} is not a valid subscriber name.", s))
-        } else {
+        let mut errors = Vec::new();
+        if s.trim().is_empty() {
+            errors.push(InvalidInput::BlankSubscriberName);
+        }
+        if s.graphemes(true).count() > 256 {
+            errors.push(InvalidInput::SubscriberNameTooLong);
+        }
+        if s.chars().any(|g| forbidden_characters.contains(&g)) {
+            errors.push(InvalidInput::ForbiddenCharactersInName(s.clone()));
+        }
+        if errors.is_empty() {
             Ok(Self(s))
+        } else {
+            Err(errors)
         }
     }
 }
@@ -25,7 +35,7 @@ impl AsRef<str> for SubscriberName {
 
 #[cfg(test)]
 mod tests {
-    use crate::domain::SubscriberName;
+    use crate::domain::{InvalidInput, SubscriberName};
     use claim::{assert_err, assert_ok};
 
     #[test]
@@ -37,13 +47,19 @@ mod tests {
     #[test]
     fn a_name_longer_than_256_graphemes_is_rejected() {
         let name = "a".repeat(257);
-        assert_err!(SubscriberName::parse(name));
+        assert_eq!(
+            assert_err!(SubscriberName::parse(name)),
+            vec![InvalidInput::SubscriberNameTooLong]
+        );
     }
 
     #[test]
     fn whitespace_only_names_are_rejected() {
         let name = " ".to_string();
-        assert_err!(SubscriberName::parse(name));
+        assert_eq!(
+            assert_err!(SubscriberName::parse(name)),
+            vec![InvalidInput::BlankSubscriberName]
+        );
     }
 
     #[test]
@@ -56,10 +72,25 @@ mod tests {
     fn names_containing_an_invalid_character_are_rejected() {
         for name in &['/', '(', ')', '"', '<', '>', '\\', '{', '}'] {
             let name = name.to_string();
-            assert_err!(SubscriberName::parse(name));
+            assert_eq!(
+                assert_err!(SubscriberName::parse(name.clone())),
+                vec![InvalidInput::ForbiddenCharactersInName(name)]
+            );
         }
     }
 
+    #[test]
+    fn every_broken_rule_is_reported() {
+        let name = format!("<{}>", "a".repeat(256));
+        assert_eq!(
+            assert_err!(SubscriberName::parse(name.clone())),
+            vec![
+                InvalidInput::SubscriberNameTooLong,
+                InvalidInput::ForbiddenCharactersInName(name)
+            ]
+        );
+    }
+
     #[test]
     fn a_valid_name_is_parsed_successfully() {
         let name = "Ursula Le Guin".to_string();
@@ -0,0 +1,93 @@
+use crate::domain::SubscriberEmail;
+use crate::email_client::{mime_message, EmailClient};
+use anyhow::Context;
+use lettre::{AsyncFileTransport, AsyncTransport, Tokio1Executor};
+use std::path::{Path, PathBuf};
+
+/// Writes every email as an `.eml` file in a directory instead of sending it.
+///
+/// Meant for local development: open the files with any mail client.
+pub struct FileEmailClient {
+    transport: AsyncFileTransport<Tokio1Executor>,
+    directory: PathBuf,
+    sender: SubscriberEmail,
+}
+
+impl FileEmailClient {
+    /// The directory is created if it does not exist yet.
+    pub fn new(directory: impl AsRef<Path>, sender: SubscriberEmail) -> std::io::Result<Self> {
+        std::fs::create_dir_all(directory.as_ref())?;
+        Ok(Self {
+            transport: AsyncFileTransport::new(directory.as_ref()),
+            directory: directory.as_ref().to_owned(),
+            sender,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailClient for FileEmailClient {
+    #[tracing::instrument(name = "Write an email to disk", skip_all)]
+    async fn send_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+    ) -> Result<(), anyhow::Error> {
+        let message = mime_message(&self.sender, recipient, subject, html_content, text_content)?;
+        let email_id = self
+            .transport
+            .send(message)
+            .await
+            .context("Failed to write the email to disk.")?;
+        tracing::info!(email_id = %email_id, "Email written to disk");
+        Ok(())
+    }
+
+    async fn check_connection(&self) -> Result<(), anyhow::Error> {
+        let metadata = tokio::fs::metadata(&self.directory)
+            .await
+            .with_context(|| format!("Cannot access {}.", self.directory.display()))?;
+        anyhow::ensure!(
+            metadata.is_dir(),
+            "{} is not a directory.",
+            self.directory.display()
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::{EmailClient, FileEmailClient};
+    use claim::assert_ok;
+
+    #[tokio::test]
+    async fn send_email_writes_an_eml_file_to_the_directory() {
+        // Arrange
+        let directory = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        let sender = SubscriberEmail::parse("sender@example.com".into()).unwrap();
+        let email_client = FileEmailClient::new(&directory, sender).unwrap();
+        let recipient = SubscriberEmail::parse("ursula@example.com".into()).unwrap();
+
+        // Act
+        let outcome = email_client
+            .send_email(&recipient, "Welcome!", "<p>Hello</p>", "Hello")
+            .await;
+
+        // Assert
+        assert_ok!(outcome);
+        let files: Vec<_> = std::fs::read_dir(&directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].extension().unwrap(), "eml");
+        let content = std::fs::read_to_string(&files[0]).unwrap();
+        assert!(content.contains("To: ursula@example.com"));
+        assert!(content.contains("Subject: Welcome!"));
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
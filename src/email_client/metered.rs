@@ -0,0 +1,52 @@
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailClient;
+use crate::metrics::Metrics;
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Wraps a backend to count and time every attempt at sending an email.
+/// Goes under `RetryingEmailClient`, so that retries are counted too and
+/// backoff is not timed.
+pub struct MeteredEmailClient {
+    inner: Arc<dyn EmailClient>,
+    /// The `backend` label, e.g. `postmark`.
+    backend: &'static str,
+    metrics: Arc<Metrics>,
+}
+
+impl MeteredEmailClient {
+    pub fn new(inner: Arc<dyn EmailClient>, backend: &'static str, metrics: Arc<Metrics>) -> Self {
+        Self {
+            inner,
+            backend,
+            metrics,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailClient for MeteredEmailClient {
+    async fn send_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+    ) -> Result<(), anyhow::Error> {
+        let start = Instant::now();
+        let outcome = self
+            .inner
+            .send_email(recipient, subject, html_content, text_content)
+            .await;
+        self.metrics.observe_email_send(
+            self.backend,
+            outcome.is_ok(),
+            start.elapsed().as_secs_f64(),
+        );
+        outcome
+    }
+
+    async fn check_connection(&self) -> Result<(), anyhow::Error> {
+        self.inner.check_connection().await
+    }
+}
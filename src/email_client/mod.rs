@@ -0,0 +1,61 @@
+mod file;
+mod metered;
+mod postmark;
+mod retry;
+mod smtp;
+
+pub use file::FileEmailClient;
+pub use metered::MeteredEmailClient;
+pub use postmark::PostmarkEmailClient;
+pub use retry::{RetryPolicy, RetryingEmailClient, TransientError};
+pub use smtp::{SmtpEmailClient, SmtpTls};
+
+use crate::domain::SubscriberEmail;
+use anyhow::Context;
+use lettre::message::{Mailbox, MultiPart};
+use lettre::Message;
+
+/// Something able to deliver an email to a subscriber.
+///
+/// Routes and the delivery worker only depend on this trait: the concrete
+/// backend is picked from `EmailClientSettings` at startup.
+#[async_trait::async_trait]
+pub trait EmailClient: Send + Sync {
+    async fn send_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+    ) -> Result<(), anyhow::Error>;
+
+    /// Check that the backend can be reached, without sending anything.
+    async fn check_connection(&self) -> Result<(), anyhow::Error>;
+}
+
+/// Build a `multipart/alternative` MIME message for the `lettre`-based backends.
+fn mime_message(
+    sender: &SubscriberEmail,
+    recipient: &SubscriberEmail,
+    subject: &str,
+    html_content: &str,
+    text_content: &str,
+) -> Result<Message, anyhow::Error> {
+    let from: Mailbox = sender
+        .as_ref()
+        .parse()
+        .context("Failed to parse the sender address.")?;
+    let to: Mailbox = recipient
+        .as_ref()
+        .parse()
+        .context("Failed to parse the recipient address.")?;
+    Message::builder()
+        .from(from)
+        .to(to)
+        .subject(subject)
+        .multipart(MultiPart::alternative_plain_html(
+            text_content.to_owned(),
+            html_content.to_owned(),
+        ))
+        .context("Failed to build the email message.")
+}
@@ -0,0 +1,283 @@
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailClient;
+use crate::routes::error_chain_fmt;
+use rand::Rng;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How `RetryingEmailClient` retries emails that failed for transient reasons.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first one.
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    /// Randomise each delay, so that clients failing together
+    /// do not retry in lockstep.
+    pub jitter: bool,
+}
+
+impl RetryPolicy {
+    /// How long to wait after the given (1-based) failed attempt.
+    ///
+    /// A `Retry-After` hint from the provider takes precedence over our own
+    /// backoff; if it asks us to wait longer than `max_delay` we give up.
+    fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Option<Duration> {
+        if let Some(retry_after) = retry_after {
+            return if retry_after <= self.max_delay {
+                Some(retry_after)
+            } else {
+                None
+            };
+        }
+        let exponential = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)));
+        let delay = exponential.min(self.max_delay);
+        if self.jitter && !delay.is_zero() {
+            // "Equal jitter": keep half of the delay, randomise the rest.
+            let half = delay / 2;
+            let jitter = rand::thread_rng().gen_range(Duration::ZERO..=half);
+            Some(half + jitter)
+        } else {
+            Some(delay)
+        }
+    }
+}
+
+/// A failure that may go away if the email is sent again later, e.g. a
+/// timeout, an HTTP 503 or an SMTP 4xx reply.
+///
+/// Backends return it (as an `anyhow::Error`) to let `RetryingEmailClient`
+/// know that another attempt is worth it: any other error is final.
+#[derive(thiserror::Error)]
+#[error("The email could not be sent for now.")]
+pub struct TransientError {
+    /// How long the provider asked us to wait before trying again.
+    pub retry_after: Option<Duration>,
+    #[source]
+    source: anyhow::Error,
+}
+
+impl TransientError {
+    pub fn new(source: impl Into<anyhow::Error>, retry_after: Option<Duration>) -> Self {
+        Self {
+            retry_after,
+            source: source.into(),
+        }
+    }
+}
+
+impl std::fmt::Debug for TransientError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+/// Wraps another backend to retry the emails it fails to send with a
+/// `TransientError`, following a `RetryPolicy`.
+pub struct RetryingEmailClient {
+    inner: Arc<dyn EmailClient>,
+    policy: RetryPolicy,
+}
+
+impl RetryingEmailClient {
+    pub fn new(inner: Arc<dyn EmailClient>, policy: RetryPolicy) -> Self {
+        Self { inner, policy }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailClient for RetryingEmailClient {
+    #[tracing::instrument(
+        name = "Send an email",
+        skip_all,
+        fields(attempts = tracing::field::Empty)
+    )]
+    async fn send_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+    ) -> Result<(), anyhow::Error> {
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            tracing::Span::current().record("attempts", attempt);
+            let e = match self
+                .inner
+                .send_email(recipient, subject, html_content, text_content)
+                .await
+            {
+                Ok(()) => return Ok(()),
+                Err(e) => e,
+            };
+            if attempt >= self.policy.max_attempts {
+                return Err(e);
+            }
+            let retry_after = match e.downcast_ref::<TransientError>() {
+                Some(transient) => transient.retry_after,
+                None => return Err(e),
+            };
+            match self.policy.delay(attempt, retry_after) {
+                Some(delay) => {
+                    tracing::warn!(
+                        error.cause_chain = ?e,
+                        error.message = %e,
+                        "Failed to send an email (attempt {}). Retrying in {:?}.",
+                        attempt,
+                        delay
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+                None => return Err(e),
+            }
+        }
+    }
+
+    async fn check_connection(&self) -> Result<(), anyhow::Error> {
+        self.inner.check_connection().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::retry::{RetryPolicy, RetryingEmailClient, TransientError};
+    use crate::email_client::EmailClient;
+    use claim::{assert_err, assert_ok};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    /// Fails with the scripted errors, in order, then succeeds.
+    struct FlakyEmailClient {
+        failures: Mutex<Vec<anyhow::Error>>,
+        attempts: Mutex<u32>,
+    }
+
+    impl FlakyEmailClient {
+        fn new(mut failures: Vec<anyhow::Error>) -> Arc<Self> {
+            failures.reverse();
+            Arc::new(Self {
+                failures: Mutex::new(failures),
+                attempts: Mutex::new(0),
+            })
+        }
+
+        fn attempts(&self) -> u32 {
+            *self.attempts.lock().unwrap()
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl EmailClient for FlakyEmailClient {
+        async fn send_email(
+            &self,
+            _recipient: &SubscriberEmail,
+            _subject: &str,
+            _html_content: &str,
+            _text_content: &str,
+        ) -> Result<(), anyhow::Error> {
+            *self.attempts.lock().unwrap() += 1;
+            match self.failures.lock().unwrap().pop() {
+                Some(e) => Err(e),
+                None => Ok(()),
+            }
+        }
+
+        async fn check_connection(&self) -> Result<(), anyhow::Error> {
+            Ok(())
+        }
+    }
+
+    fn transient(retry_after: Option<Duration>) -> anyhow::Error {
+        TransientError::new(anyhow::anyhow!("503 Service Unavailable"), retry_after).into()
+    }
+
+    /// A fast retry policy, to keep the test suite snappy.
+    fn retry_policy() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(10),
+            max_delay: Duration::from_millis(100),
+            jitter: false,
+        }
+    }
+
+    async fn send(client: Arc<FlakyEmailClient>) -> Result<(), anyhow::Error> {
+        let recipient = SubscriberEmail::parse("ursula@example.com".into()).unwrap();
+        RetryingEmailClient::new(client, retry_policy())
+            .send_email(&recipient, "Subject", "<p>Body</p>", "Body")
+            .await
+    }
+
+    #[tokio::test]
+    async fn transient_failures_are_retried() {
+        let client = FlakyEmailClient::new(vec![transient(None), transient(None)]);
+
+        assert_ok!(send(client.clone()).await);
+        assert_eq!(client.attempts(), 3);
+    }
+
+    #[tokio::test]
+    async fn other_failures_are_not_retried() {
+        let client = FlakyEmailClient::new(vec![anyhow::anyhow!("422 Unprocessable Entity")]);
+
+        assert_err!(send(client.clone()).await);
+        assert_eq!(client.attempts(), 1);
+    }
+
+    #[tokio::test]
+    async fn transient_failures_wrapped_in_context_are_retried() {
+        let client = FlakyEmailClient::new(vec![transient(None).context("Failed to send.")]);
+
+        assert_ok!(send(client.clone()).await);
+        assert_eq!(client.attempts(), 2);
+    }
+
+    #[tokio::test]
+    async fn it_gives_up_after_max_attempts() {
+        let failures = (0..5).map(|_| transient(None)).collect();
+        let client = FlakyEmailClient::new(failures);
+
+        assert_err!(send(client.clone()).await);
+        assert_eq!(client.attempts(), 3);
+    }
+
+    #[tokio::test]
+    async fn it_gives_up_if_retry_after_exceeds_the_max_delay() {
+        let client = FlakyEmailClient::new(vec![transient(Some(Duration::from_secs(3600)))]);
+
+        assert_err!(send(client.clone()).await);
+        assert_eq!(client.attempts(), 1);
+    }
+
+    #[test]
+    fn backoff_grows_exponentially_up_to_the_max_delay() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(1000),
+            jitter: false,
+        };
+        assert_eq!(policy.delay(1, None), Some(Duration::from_millis(100)));
+        assert_eq!(policy.delay(2, None), Some(Duration::from_millis(200)));
+        assert_eq!(policy.delay(4, None), Some(Duration::from_millis(800)));
+        assert_eq!(policy.delay(5, None), Some(Duration::from_millis(1000)));
+    }
+
+    #[test]
+    fn jitter_keeps_the_delay_between_half_and_full_backoff() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(1000),
+            jitter: true,
+        };
+        for _ in 0..100 {
+            let delay = policy.delay(3, None).unwrap();
+            assert!(delay >= Duration::from_millis(200) && delay <= Duration::from_millis(400));
+        }
+    }
+}
@@ -0,0 +1,275 @@
+use crate::domain::SubscriberEmail;
+use crate::email_client::{mime_message, EmailClient, TransientError};
+use anyhow::Context;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Tokio1Executor};
+use secrecy::{ExposeSecret, Secret};
+use std::time::Duration;
+
+/// How the connection to the SMTP relay is secured.
+#[derive(serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    /// Plain text connection. Only suitable for a relay on the local network.
+    None,
+    /// Upgrade a plain text connection with `STARTTLS`.
+    StartTls,
+    /// Implicit TLS from the first byte (usually port 465).
+    Tls,
+}
+
+/// Sends emails through an SMTP relay.
+pub struct SmtpEmailClient {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    sender: SubscriberEmail,
+}
+
+impl SmtpEmailClient {
+    pub fn new(
+        host: &str,
+        port: u16,
+        tls: SmtpTls,
+        credentials: Option<(String, Secret<String>)>,
+        sender: SubscriberEmail,
+        timeout: Duration,
+    ) -> Result<Self, anyhow::Error> {
+        let builder = match tls {
+            SmtpTls::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host),
+            SmtpTls::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)
+                .context("Failed to configure the SMTP relay.")?,
+            SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(host)
+                .context("Failed to configure the SMTP relay.")?,
+        };
+        let mut builder = builder.port(port).timeout(Some(timeout));
+        if let Some((username, password)) = credentials {
+            builder = builder.credentials(Credentials::new(
+                username,
+                password.expose_secret().to_owned(),
+            ));
+        }
+        Ok(Self {
+            transport: builder.build(),
+            sender,
+        })
+    }
+}
+
+/// 4xx replies, timeouts and failures to reach or talk to the relay are
+/// worth retrying. 5xx replies, TLS errors and malformed messages are not.
+fn is_transient(e: &lettre::transport::smtp::Error) -> bool {
+    !(e.is_permanent() || e.is_client() || e.is_response() || e.is_tls())
+}
+
+#[async_trait::async_trait]
+impl EmailClient for SmtpEmailClient {
+    #[tracing::instrument(name = "Send an email over SMTP", skip_all)]
+    async fn send_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+    ) -> Result<(), anyhow::Error> {
+        let message = mime_message(&self.sender, recipient, subject, html_content, text_content)?;
+        match self.transport.send(message).await {
+            Ok(_) => Ok(()),
+            Err(e) if is_transient(&e) => Err(TransientError::new(e, None))
+                .context("The SMTP relay failed to accept the email."),
+            Err(e) => Err(e).context("The SMTP relay rejected the email."),
+        }
+    }
+
+    async fn check_connection(&self) -> Result<(), anyhow::Error> {
+        if self
+            .transport
+            .test_connection()
+            .await
+            .context("Failed to connect to the SMTP relay.")?
+        {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!("The SMTP relay did not answer NOOP."))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::{
+        EmailClient, RetryPolicy, RetryingEmailClient, SmtpEmailClient, SmtpTls, TransientError,
+    };
+    use claim::{assert_err, assert_none, assert_ok, assert_some};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    /// A bare-bones SMTP relay, answering `MAIL FROM` with each of the
+    /// scripted replies in turn (repeating the last one) and recording the
+    /// messages it accepts.
+    struct SmtpStub {
+        port: u16,
+        messages: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl SmtpStub {
+        async fn start(mail_replies: Vec<&'static str>) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let messages = Arc::new(Mutex::new(Vec::new()));
+            let replies = Arc::new(Mutex::new(mail_replies));
+            let received = messages.clone();
+            tokio::spawn(async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    let mail_reply = {
+                        let mut replies = replies.lock().unwrap();
+                        if replies.len() > 1 {
+                            replies.remove(0)
+                        } else {
+                            replies[0]
+                        }
+                    };
+                    let received = received.clone();
+                    tokio::spawn(async move {
+                        let (reader, mut writer) = stream.into_split();
+                        let mut lines = BufReader::new(reader).lines();
+                        writer.write_all(b"220 stub ESMTP\r\n").await.unwrap();
+                        while let Ok(Some(line)) = lines.next_line().await {
+                            let command = line.to_uppercase();
+                            let reply =
+                                if command.starts_with("EHLO") || command.starts_with("HELO") {
+                                    "250 stub"
+                                } else if command.starts_with("MAIL") {
+                                    mail_reply
+                                } else if command.starts_with("DATA") {
+                                    writer.write_all(b"354 Go ahead\r\n").await.unwrap();
+                                    let mut message = String::new();
+                                    while let Ok(Some(line)) = lines.next_line().await {
+                                        if line == "." {
+                                            break;
+                                        }
+                                        message.push_str(&line);
+                                        message.push('\n');
+                                    }
+                                    received.lock().unwrap().push(message);
+                                    "250 Queued"
+                                } else if command.starts_with("QUIT") {
+                                    let _ = writer.write_all(b"221 Bye\r\n").await;
+                                    break;
+                                } else {
+                                    "250 OK"
+                                };
+                            writer.write_all(reply.as_bytes()).await.unwrap();
+                            writer.write_all(b"\r\n").await.unwrap();
+                        }
+                    });
+                }
+            });
+            Self { port, messages }
+        }
+
+        fn messages(&self) -> Vec<String> {
+            self.messages.lock().unwrap().clone()
+        }
+    }
+
+    fn email() -> SubscriberEmail {
+        SubscriberEmail::parse("ursula@example.com".into()).unwrap()
+    }
+
+    fn email_client(port: u16) -> SmtpEmailClient {
+        SmtpEmailClient::new(
+            "127.0.0.1",
+            port,
+            SmtpTls::None,
+            None,
+            email(),
+            Duration::from_secs(1),
+        )
+        .unwrap()
+    }
+
+    async fn send(email_client: &SmtpEmailClient) -> Result<(), anyhow::Error> {
+        email_client
+            .send_email(&email(), "Newsletter title", "<p>Body</p>", "Body")
+            .await
+    }
+
+    fn transient_error(outcome: &Result<(), anyhow::Error>) -> Option<&TransientError> {
+        outcome.as_ref().err()?.downcast_ref::<TransientError>()
+    }
+
+    #[tokio::test]
+    async fn send_email_hands_the_message_to_the_relay() {
+        let relay = SmtpStub::start(vec!["250 OK"]).await;
+
+        assert_ok!(send(&email_client(relay.port)).await);
+
+        let messages = relay.messages();
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].contains("Subject: Newsletter title"));
+    }
+
+    #[tokio::test]
+    async fn a_4xx_reply_is_transient() {
+        let relay = SmtpStub::start(vec!["451 4.3.0 Try again later"]).await;
+
+        let outcome = send(&email_client(relay.port)).await;
+
+        assert_some!(transient_error(&outcome));
+        assert!(relay.messages().is_empty());
+    }
+
+    #[tokio::test]
+    async fn a_5xx_reply_is_not_transient() {
+        let relay = SmtpStub::start(vec!["550 5.1.1 No such user"]).await;
+
+        let outcome = send(&email_client(relay.port)).await;
+
+        assert_err!(&outcome);
+        assert_none!(transient_error(&outcome));
+    }
+
+    #[tokio::test]
+    async fn failing_to_connect_is_transient() {
+        // Grab a free port, then stop listening on it.
+        let port = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+
+        let outcome = send(&email_client(port)).await;
+
+        assert_some!(transient_error(&outcome));
+    }
+
+    #[tokio::test]
+    async fn a_retrying_client_resends_after_a_4xx_reply() {
+        let relay = SmtpStub::start(vec!["451 4.3.0 Try again later", "250 OK"]).await;
+        let retry_policy = RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(10),
+            max_delay: Duration::from_millis(100),
+            jitter: false,
+        };
+        let email_client =
+            RetryingEmailClient::new(Arc::new(email_client(relay.port)), retry_policy);
+
+        let outcome = email_client
+            .send_email(&email(), "Newsletter title", "<p>Body</p>", "Body")
+            .await;
+
+        assert_ok!(outcome);
+        assert_eq!(relay.messages().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn check_connection_succeeds_against_a_live_relay() {
+        let relay = SmtpStub::start(vec!["250 OK"]).await;
+
+        assert_ok!(email_client(relay.port).check_connection().await);
+    }
+}
@@ -0,0 +1,468 @@
+use crate::configuration::EmailTemplateSettings;
+use crate::domain::Locale;
+use anyhow::Context;
+use std::path::Path;
+use tera::Tera;
+
+/// The templates of every email in every locale, with the defaults compiled
+/// into the binary. A file at the same path in the configured directory,
+/// e.g. `fr/confirmation.html`, wins.
+const TEMPLATES: &[(&str, &str)] = &[
+    (
+        "en/confirmation_subject.txt",
+        include_str!("../templates/email/en/confirmation_subject.txt"),
+    ),
+    (
+        "en/confirmation.html",
+        include_str!("../templates/email/en/confirmation.html"),
+    ),
+    (
+        "en/confirmation.txt",
+        include_str!("../templates/email/en/confirmation.txt"),
+    ),
+    (
+        "en/already_subscribed_subject.txt",
+        include_str!("../templates/email/en/already_subscribed_subject.txt"),
+    ),
+    (
+        "en/already_subscribed.html",
+        include_str!("../templates/email/en/already_subscribed.html"),
+    ),
+    (
+        "en/already_subscribed.txt",
+        include_str!("../templates/email/en/already_subscribed.txt"),
+    ),
+    (
+        "en/newsletter_issue_subject.txt",
+        include_str!("../templates/email/en/newsletter_issue_subject.txt"),
+    ),
+    (
+        "en/newsletter_issue.html",
+        include_str!("../templates/email/en/newsletter_issue.html"),
+    ),
+    (
+        "en/newsletter_issue.txt",
+        include_str!("../templates/email/en/newsletter_issue.txt"),
+    ),
+    (
+        "en/digest_subject.txt",
+        include_str!("../templates/email/en/digest_subject.txt"),
+    ),
+    (
+        "en/digest.html",
+        include_str!("../templates/email/en/digest.html"),
+    ),
+    (
+        "en/digest.txt",
+        include_str!("../templates/email/en/digest.txt"),
+    ),
+    (
+        "fr/confirmation_subject.txt",
+        include_str!("../templates/email/fr/confirmation_subject.txt"),
+    ),
+    (
+        "fr/confirmation.html",
+        include_str!("../templates/email/fr/confirmation.html"),
+    ),
+    (
+        "fr/confirmation.txt",
+        include_str!("../templates/email/fr/confirmation.txt"),
+    ),
+    (
+        "fr/already_subscribed_subject.txt",
+        include_str!("../templates/email/fr/already_subscribed_subject.txt"),
+    ),
+    (
+        "fr/already_subscribed.html",
+        include_str!("../templates/email/fr/already_subscribed.html"),
+    ),
+    (
+        "fr/already_subscribed.txt",
+        include_str!("../templates/email/fr/already_subscribed.txt"),
+    ),
+    (
+        "fr/newsletter_issue_subject.txt",
+        include_str!("../templates/email/fr/newsletter_issue_subject.txt"),
+    ),
+    (
+        "fr/newsletter_issue.html",
+        include_str!("../templates/email/fr/newsletter_issue.html"),
+    ),
+    (
+        "fr/newsletter_issue.txt",
+        include_str!("../templates/email/fr/newsletter_issue.txt"),
+    ),
+    (
+        "fr/digest_subject.txt",
+        include_str!("../templates/email/fr/digest_subject.txt"),
+    ),
+    (
+        "fr/digest.html",
+        include_str!("../templates/email/fr/digest.html"),
+    ),
+    (
+        "fr/digest.txt",
+        include_str!("../templates/email/fr/digest.txt"),
+    ),
+];
+
+/// Each email is made of `<name>_subject.txt`, `<name>.html` and
+/// `<name>.txt`.
+const CONFIRMATION: &str = "confirmation";
+const ALREADY_SUBSCRIBED: &str = "already_subscribed";
+const NEWSLETTER_ISSUE: &str = "newsletter_issue";
+const DIGEST: &str = "digest";
+
+/// Variables available to `confirmation.{html,txt}`.
+#[derive(serde::Serialize)]
+pub struct ConfirmationEmail<'a> {
+    pub subscriber_name: &'a str,
+    pub confirmation_link: &'a str,
+    pub preferences_link: &'a str,
+}
+
+/// Variables available to `already_subscribed.{html,txt}`, sent instead of
+/// a confirmation when the address has already confirmed every list it
+/// signed up to.
+#[derive(serde::Serialize)]
+pub struct AlreadySubscribedEmail<'a> {
+    pub subscriber_name: &'a str,
+    pub preferences_link: &'a str,
+}
+
+/// Variables available to `newsletter_issue.{html,txt}`.
+///
+/// `html_content` is the body of the issue as written by its author: the
+/// HTML template has to mark it `safe` to keep its markup.
+#[derive(serde::Serialize)]
+pub struct NewsletterIssueEmail<'a> {
+    pub title: &'a str,
+    pub html_content: &'a str,
+    pub text_content: &'a str,
+    pub unsubscribe_link: &'a str,
+    pub preferences_link: &'a str,
+}
+
+/// Variables available to `digest.{html,txt}`, sent instead of
+/// `newsletter_issue` when several issues are delivered at once to a
+/// subscriber who asked for a daily or weekly digest.
+#[derive(serde::Serialize)]
+pub struct DigestEmail<'a> {
+    pub issues: &'a [DigestIssue<'a>],
+    pub unsubscribe_link: &'a str,
+    pub preferences_link: &'a str,
+}
+
+/// An issue of a `DigestEmail`, oldest first. As in `NewsletterIssueEmail`,
+/// `html_content` has to be marked `safe`.
+#[derive(serde::Serialize)]
+pub struct DigestIssue<'a> {
+    pub title: &'a str,
+    pub html_content: &'a str,
+    pub text_content: &'a str,
+}
+
+pub struct RenderedEmail {
+    pub subject: String,
+    pub html: String,
+    pub text: String,
+}
+
+/// Bodies of the emails we send, rendered with Tera.
+///
+/// Variables are HTML-escaped in `.html` templates and left as they are in
+/// `.txt` ones.
+pub struct EmailTemplates {
+    tera: Tera,
+}
+
+impl EmailTemplates {
+    /// Load the templates and check that they render.
+    ///
+    /// Tera only finds out about a variable it does not know when rendering,
+    /// so every template is rendered once with placeholder values: a typo in
+    /// a template stops the application at startup rather than failing
+    /// every email it sends.
+    pub fn load(settings: &EmailTemplateSettings) -> Result<Self, anyhow::Error> {
+        let mut tera = Tera::default();
+        tera.set_escape_fn(escape_html);
+        let directory = Path::new(&settings.directory);
+        let mut templates = Vec::with_capacity(TEMPLATES.len());
+        for (name, default) in TEMPLATES {
+            let path = directory.join(name);
+            let source = if path.is_file() {
+                std::fs::read_to_string(&path)
+                    .with_context(|| format!("Failed to read {}", path.display()))?
+            } else {
+                (*default).to_owned()
+            };
+            templates.push((*name, source));
+        }
+        tera.add_raw_templates(templates)
+            .context("Failed to parse the email templates")?;
+        let templates = Self { tera };
+        for locale in Locale::ALL {
+            templates
+                .confirmation(
+                    locale,
+                    &ConfirmationEmail {
+                        subscriber_name: "name",
+                        confirmation_link: "https://example.com/confirm",
+                        preferences_link: "https://example.com/preferences",
+                    },
+                )
+                .context("Invalid confirmation email template")?;
+            templates
+                .already_subscribed(
+                    locale,
+                    &AlreadySubscribedEmail {
+                        subscriber_name: "name",
+                        preferences_link: "https://example.com/preferences",
+                    },
+                )
+                .context("Invalid already subscribed email template")?;
+            templates
+                .newsletter_issue(
+                    locale,
+                    &NewsletterIssueEmail {
+                        title: "title",
+                        html_content: "<p>content</p>",
+                        text_content: "content",
+                        unsubscribe_link: "https://example.com/unsubscribe",
+                        preferences_link: "https://example.com/preferences",
+                    },
+                )
+                .context("Invalid newsletter issue template")?;
+            let issue = DigestIssue {
+                title: "title",
+                html_content: "<p>content</p>",
+                text_content: "content",
+            };
+            templates
+                .digest(
+                    locale,
+                    &DigestEmail {
+                        issues: &[issue],
+                        unsubscribe_link: "https://example.com/unsubscribe",
+                        preferences_link: "https://example.com/preferences",
+                    },
+                )
+                .context("Invalid digest template")?;
+        }
+        Ok(templates)
+    }
+
+    pub fn confirmation(
+        &self,
+        locale: Locale,
+        email: &ConfirmationEmail,
+    ) -> Result<RenderedEmail, anyhow::Error> {
+        self.render(locale, CONFIRMATION, email)
+    }
+
+    pub fn already_subscribed(
+        &self,
+        locale: Locale,
+        email: &AlreadySubscribedEmail,
+    ) -> Result<RenderedEmail, anyhow::Error> {
+        self.render(locale, ALREADY_SUBSCRIBED, email)
+    }
+
+    pub fn newsletter_issue(
+        &self,
+        locale: Locale,
+        email: &NewsletterIssueEmail,
+    ) -> Result<RenderedEmail, anyhow::Error> {
+        self.render(locale, NEWSLETTER_ISSUE, email)
+    }
+
+    pub fn digest(
+        &self,
+        locale: Locale,
+        email: &DigestEmail,
+    ) -> Result<RenderedEmail, anyhow::Error> {
+        self.render(locale, DIGEST, email)
+    }
+
+    fn render(
+        &self,
+        locale: Locale,
+        email: &str,
+        variables: &impl serde::Serialize,
+    ) -> Result<RenderedEmail, anyhow::Error> {
+        let context = tera::Context::from_serialize(variables)
+            .context("Failed to build the template context")?;
+        let render = |suffix: &str| {
+            let template = format!("{}/{}{}", locale.as_str(), email, suffix);
+            self.tera
+                .render(&template, &context)
+                .with_context(|| format!("Failed to render {}", template))
+        };
+        Ok(RenderedEmail {
+            subject: render("_subject.txt")?.trim().to_owned(),
+            html: render(".html")?,
+            text: render(".txt")?,
+        })
+    }
+}
+
+/// Unlike Tera's own escaping, this leaves `/` alone so that links stay
+/// readable in the source of the email.
+fn escape_html(input: &str) -> String {
+    htmlescape::encode_minimal(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ConfirmationEmail, DigestEmail, DigestIssue, EmailTemplates};
+    use crate::configuration::EmailTemplateSettings;
+    use crate::domain::Locale;
+    use std::path::PathBuf;
+
+    fn template_directory(templates: &[(&str, &str)]) -> PathBuf {
+        let directory = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        std::fs::create_dir_all(&directory).unwrap();
+        for (name, source) in templates {
+            let path = directory.join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, source).unwrap();
+        }
+        directory
+    }
+
+    fn settings(directory: &std::path::Path) -> EmailTemplateSettings {
+        EmailTemplateSettings {
+            directory: directory.to_str().unwrap().to_owned(),
+        }
+    }
+
+    fn confirmation_email() -> ConfirmationEmail<'static> {
+        ConfirmationEmail {
+            subscriber_name: "<b>Ursula</b>",
+            confirmation_link: "https://example.com/confirm?a=1&b=2",
+            preferences_link: "https://example.com/preferences",
+        }
+    }
+
+    #[test]
+    fn the_compiled_in_defaults_are_used_when_the_directory_does_not_exist() {
+        let directory = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        let templates = EmailTemplates::load(&settings(&directory)).unwrap();
+
+        let email = templates
+            .confirmation(Locale::En, &confirmation_email())
+            .unwrap();
+
+        assert!(email.text.contains("https://example.com/confirm?a=1&b=2"));
+        assert!(email
+            .html
+            .contains("https://example.com/confirm?a=1&amp;b=2"));
+    }
+
+    #[test]
+    fn templates_in_the_directory_override_the_defaults() {
+        let directory =
+            template_directory(&[("en/confirmation.txt", "Hello {{ subscriber_name }}!")]);
+        let templates = EmailTemplates::load(&settings(&directory)).unwrap();
+
+        let email = templates
+            .confirmation(Locale::En, &confirmation_email())
+            .unwrap();
+
+        assert_eq!(email.text, "Hello <b>Ursula</b>!");
+        assert!(email.html.contains("Welcome to our newsletter!"));
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn emails_are_rendered_in_the_requested_locale() {
+        let directory = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        let templates = EmailTemplates::load(&settings(&directory)).unwrap();
+
+        let english = templates
+            .confirmation(Locale::En, &confirmation_email())
+            .unwrap();
+        let french = templates
+            .confirmation(Locale::Fr, &confirmation_email())
+            .unwrap();
+
+        assert_eq!(english.subject, "Welcome!");
+        assert_eq!(french.subject, "Bienvenue !");
+        assert!(french.text.contains("Bienvenue dans notre newsletter"));
+    }
+
+    #[test]
+    fn variables_are_escaped_in_html_templates_only() {
+        let directory = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        let templates = EmailTemplates::load(&settings(&directory)).unwrap();
+
+        let email = templates
+            .confirmation(Locale::En, &confirmation_email())
+            .unwrap();
+
+        assert!(email.html.contains("&lt;b&gt;Ursula&lt;/b&gt;"));
+        assert!(!email.html.contains("<b>Ursula</b>"));
+        assert!(email.text.contains("<b>Ursula</b>"));
+    }
+
+    #[test]
+    fn a_template_referencing_an_unknown_variable_is_rejected_at_load_time() {
+        let directory = template_directory(&[("fr/confirmation.html", "Salut {{ first_name }}")]);
+
+        let outcome = EmailTemplates::load(&settings(&directory));
+
+        assert!(outcome.is_err());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn a_template_with_a_syntax_error_is_rejected_at_load_time() {
+        let directory = template_directory(&[("en/newsletter_issue.txt", "{{ text_content ")]);
+
+        let outcome = EmailTemplates::load(&settings(&directory));
+
+        assert!(outcome.is_err());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn a_digest_lists_every_issue_in_order() {
+        let directory = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        let templates = EmailTemplates::load(&settings(&directory)).unwrap();
+        let issues = [
+            DigestIssue {
+                title: "First",
+                html_content: "<p>first</p>",
+                text_content: "first",
+            },
+            DigestIssue {
+                title: "Second",
+                html_content: "<p>second</p>",
+                text_content: "second",
+            },
+        ];
+
+        let email = templates
+            .digest(
+                Locale::En,
+                &DigestEmail {
+                    issues: &issues,
+                    unsubscribe_link: "https://example.com/unsubscribe",
+                    preferences_link: "https://example.com/preferences",
+                },
+            )
+            .unwrap();
+
+        assert_eq!(email.subject, "Your digest: 2 new issues");
+        assert!(
+            email.html.find("<p>first</p>").unwrap() < email.html.find("<p>second</p>").unwrap()
+        );
+        assert!(email.text.find("First").unwrap() < email.text.find("Second").unwrap());
+    }
+
+    #[test]
+    fn the_shipped_templates_are_valid() {
+        let directory = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("templates/email");
+        assert!(EmailTemplates::load(&settings(&directory)).is_ok());
+    }
+}
@@ -0,0 +1,186 @@
+//! Readiness of the application to serve traffic, as seen by its
+//! dependencies.
+use crate::email_client::EmailClient;
+use crate::shutdown::Shutdown;
+use anyhow::Context;
+use sqlx::migrate::Migrator;
+use sqlx::PgPool;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// The migrations this build expects to find applied.
+static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReadinessStatus {
+    Ready,
+    NotReady,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DependencyStatus {
+    Up,
+    Down,
+}
+
+#[derive(Debug, serde::Serialize, utoipa::ToSchema)]
+pub struct DependencyCheck {
+    pub status: DependencyStatus,
+    /// How long the check took, failures and timeouts included.
+    pub latency_ms: u64,
+}
+
+#[derive(Debug, serde::Serialize, utoipa::ToSchema)]
+pub struct Readiness {
+    pub status: ReadinessStatus,
+    /// Keyed by dependency: `database`, `migrations` and, when checked,
+    /// `email_provider`. Empty while shutting down.
+    pub checks: BTreeMap<&'static str, DependencyCheck>,
+}
+
+/// Checks run on every call to `GET /health/ready`.
+///
+/// Failures are logged rather than returned: the probe is public and the
+/// errors would tell too much about our infrastructure.
+pub struct ReadinessProbe {
+    pool: PgPool,
+    /// `None` when the email provider is left out of the checks.
+    email_client: Option<Arc<dyn EmailClient>>,
+    timeout: Duration,
+    /// Once triggered, we are not ready whatever the state of our
+    /// dependencies, so that traffic is routed elsewhere.
+    shutdown: Shutdown,
+}
+
+impl ReadinessProbe {
+    pub fn new(
+        pool: PgPool,
+        email_client: Option<Arc<dyn EmailClient>>,
+        timeout: Duration,
+        shutdown: Shutdown,
+    ) -> Self {
+        Self {
+            pool,
+            email_client,
+            timeout,
+            shutdown,
+        }
+    }
+
+    /// The checks run concurrently, each within the configured timeout.
+    pub async fn check(&self) -> Readiness {
+        if self.shutdown.is_triggered() {
+            return Readiness {
+                status: ReadinessStatus::NotReady,
+                checks: BTreeMap::new(),
+            };
+        }
+        let email_provider = async {
+            match &self.email_client {
+                Some(email_client) => Some(
+                    self.run_check("email_provider", email_client.check_connection())
+                        .await,
+                ),
+                None => None,
+            }
+        };
+        let (database, migrations, email_provider) = tokio::join!(
+            self.run_check("database", ping(&self.pool)),
+            self.run_check("migrations", check_migrations(&self.pool)),
+            email_provider,
+        );
+        let mut checks = BTreeMap::new();
+        checks.insert("database", database);
+        checks.insert("migrations", migrations);
+        if let Some(email_provider) = email_provider {
+            checks.insert("email_provider", email_provider);
+        }
+        let status = if checks.values().all(|c| c.status == DependencyStatus::Up) {
+            ReadinessStatus::Ready
+        } else {
+            ReadinessStatus::NotReady
+        };
+        Readiness { status, checks }
+    }
+
+    async fn run_check(
+        &self,
+        dependency: &'static str,
+        check: impl Future<Output = Result<(), anyhow::Error>>,
+    ) -> DependencyCheck {
+        let start = Instant::now();
+        let outcome = match tokio::time::timeout(self.timeout, check).await {
+            Ok(outcome) => outcome,
+            Err(_) => Err(anyhow::anyhow!("Timed out after {:?}.", self.timeout)),
+        };
+        let latency_ms = start.elapsed().as_millis() as u64;
+        let status = match outcome {
+            Ok(()) => DependencyStatus::Up,
+            Err(e) => {
+                tracing::warn!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    dependency,
+                    "A readiness check failed"
+                );
+                DependencyStatus::Down
+            }
+        };
+        DependencyCheck { status, latency_ms }
+    }
+}
+
+async fn ping(pool: &PgPool) -> Result<(), anyhow::Error> {
+    sqlx::query("SELECT 1")
+        .execute(pool)
+        .await
+        .context("Failed to reach the database.")?;
+    Ok(())
+}
+
+/// Every migration embedded in the binary must have been applied
+/// successfully.
+async fn check_migrations(pool: &PgPool) -> Result<(), anyhow::Error> {
+    let applied: Vec<i64> =
+        sqlx::query_scalar("SELECT version FROM _sqlx_migrations WHERE success")
+            .fetch_all(pool)
+            .await
+            .context("Failed to list the applied migrations.")?;
+    let pending: Vec<String> = MIGRATOR
+        .iter()
+        .filter(|m| !applied.contains(&m.version))
+        .map(|m| m.version.to_string())
+        .collect();
+    anyhow::ensure!(
+        pending.is_empty(),
+        "Migrations not applied yet: {}.",
+        pending.join(", ")
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ReadinessProbe, ReadinessStatus};
+    use crate::shutdown::Shutdown;
+    use sqlx::PgPool;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn the_probe_fails_without_checking_anything_once_shutting_down() {
+        // Nothing listens there: any check would fail.
+        let pool = PgPool::connect_lazy("postgres://postgres@127.0.0.1:1/unreachable").unwrap();
+        let shutdown = Shutdown::new();
+        let probe = ReadinessProbe::new(pool, None, Duration::from_secs(1), shutdown.clone());
+
+        shutdown.trigger();
+        let readiness = probe.check().await;
+
+        assert_eq!(readiness.status, ReadinessStatus::NotReady);
+        assert!(readiness.checks.is_empty());
+    }
+}
@@ -0,0 +1,388 @@
+use crate::configuration::Settings;
+use crate::domain::{DigestFrequency, Locale, SubscriberEmail};
+use crate::email_client::EmailClient;
+use crate::email_templates::{DigestEmail, DigestIssue, EmailTemplates, NewsletterIssueEmail};
+use crate::metrics::Metrics;
+use crate::shutdown::Shutdown;
+use crate::startup::get_connection_pool;
+use crate::subscriber_links::SubscriberLinks;
+use chrono::Utc;
+use sqlx::{PgPool, Postgres, Transaction};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::field::display;
+use tracing::Span;
+use uuid::Uuid;
+
+/// How many times a failed delivery is attempted again before the task is dropped.
+const MAX_RETRIES: i16 = 5;
+
+pub enum ExecutionOutcome {
+    TaskCompleted,
+    EmptyQueue,
+}
+
+/// Deliver the next due issue, or, to a subscriber who asked for a daily or
+/// weekly digest, every issue due for them in a single email.
+#[tracing::instrument(
+    skip_all,
+    fields(
+        subscriber_email=tracing::field::Empty,
+        n_issues=tracing::field::Empty
+    ),
+    err
+)]
+pub async fn try_execute_task(
+    pool: &PgPool,
+    email_client: &dyn EmailClient,
+    templates: &EmailTemplates,
+    links: &SubscriberLinks,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let tasks = dequeue_tasks(pool).await?;
+    if tasks.is_none() {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    }
+    let (mut transaction, tasks) = tasks.unwrap();
+    let subscriber_email = tasks[0].subscriber_email.clone();
+    Span::current()
+        .record("subscriber_email", display(&subscriber_email))
+        .record("n_issues", tasks.len());
+    match SubscriberEmail::parse(subscriber_email) {
+        Ok(email) => {
+            // Issues of the lists the subscriber left since are skipped.
+            let mut recipient = None;
+            let mut delivered = Vec::with_capacity(tasks.len());
+            let mut issues = Vec::with_capacity(tasks.len());
+            for task in &tasks {
+                if let Some(subscriber) =
+                    get_confirmed_subscriber_id(pool, &email, task.newsletter_issue_id).await?
+                {
+                    recipient = Some(subscriber);
+                    delivered.push(task);
+                    issues.push(get_issue(pool, task.newsletter_issue_id).await?);
+                }
+            }
+            match recipient {
+                Some((subscriber_id, locale)) => {
+                    let unsubscribe_link = links.unsubscribe_link(subscriber_id);
+                    let preferences_link = links.preferences_link(subscriber_id);
+                    let body = if let [issue] = issues.as_slice() {
+                        templates.newsletter_issue(
+                            locale,
+                            &NewsletterIssueEmail {
+                                title: &issue.title,
+                                html_content: &issue.html_content,
+                                text_content: &issue.text_content,
+                                unsubscribe_link: &unsubscribe_link,
+                                preferences_link: &preferences_link,
+                            },
+                        )?
+                    } else {
+                        let issues: Vec<_> = issues
+                            .iter()
+                            .map(|issue| DigestIssue {
+                                title: &issue.title,
+                                html_content: &issue.html_content,
+                                text_content: &issue.text_content,
+                            })
+                            .collect();
+                        templates.digest(
+                            locale,
+                            &DigestEmail {
+                                issues: &issues,
+                                unsubscribe_link: &unsubscribe_link,
+                                preferences_link: &preferences_link,
+                            },
+                        )?
+                    };
+                    let outcome = match email_client
+                        .send_email(&email, &body.subject, &body.html, &body.text)
+                        .await
+                    {
+                        Ok(()) => "delivered",
+                        Err(e) => {
+                            let n_retries = tasks.iter().map(|t| t.n_retries).max().unwrap_or(0);
+                            if n_retries < MAX_RETRIES {
+                                tracing::warn!(
+                                    error.cause_chain = ?e,
+                                    error.message = %e,
+                                    "Failed to deliver issue to a confirmed subscriber. \
+                                    The task will be retried later.",
+                                );
+                                postpone_tasks(transaction, &tasks, n_retries).await?;
+                                return Ok(ExecutionOutcome::TaskCompleted);
+                            }
+                            tracing::error!(
+                                error.cause_chain = ?e,
+                                error.message = %e,
+                                "Failed to deliver issue to a confirmed subscriber. \
+                                Giving up after {} retries.",
+                                n_retries,
+                            );
+                            "failed"
+                        }
+                    };
+                    for task in delivered {
+                        record_delivery(&mut transaction, task, subscriber_id, outcome).await?;
+                    }
+                }
+                None => {
+                    tracing::info!(
+                        "Skipping a subscriber who is no longer confirmed, \
+                    e.g. because they unsubscribed after the issue was published",
+                    );
+                }
+            }
+        }
+        Err(e) => {
+            tracing::warn!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Skipping a confirmed subscriber. \
+                Their stored contact details are invalid",
+            );
+        }
+    }
+    delete_tasks(transaction, &tasks).await?;
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+type PgTransaction = Transaction<'static, Postgres>;
+
+struct Task {
+    newsletter_issue_id: Uuid,
+    subscriber_email: String,
+    n_retries: i16,
+}
+
+/// Lock a due task and, if its subscriber gets digests, every other task
+/// due for them, oldest issue first.
+#[tracing::instrument(skip_all)]
+async fn dequeue_tasks(pool: &PgPool) -> Result<Option<(PgTransaction, Vec<Task>)>, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let r = sqlx::query!(
+        r#"
+        SELECT
+            q.newsletter_issue_id,
+            q.subscriber_email,
+            q.n_retries,
+            (
+                SELECT digest_frequency
+                FROM subscriptions s
+                WHERE s.email = q.subscriber_email
+            ) AS "digest_frequency?"
+        FROM issue_delivery_queue q
+        WHERE q.execute_after <= now()
+        FOR UPDATE
+        SKIP LOCKED
+        LIMIT 1
+        "#,
+    )
+    .fetch_optional(&mut transaction)
+    .await?;
+    let r = match r {
+        Some(r) => r,
+        None => return Ok(None),
+    };
+    let digest_frequency = r
+        .digest_frequency
+        .and_then(|f| DigestFrequency::parse(f).ok())
+        .unwrap_or(DigestFrequency::Immediate);
+    let tasks = if digest_frequency == DigestFrequency::Immediate {
+        vec![Task {
+            newsletter_issue_id: r.newsletter_issue_id,
+            subscriber_email: r.subscriber_email,
+            n_retries: r.n_retries,
+        }]
+    } else {
+        // Locks we already hold are not skipped: the task above is included.
+        sqlx::query_as!(
+            Task,
+            r#"
+            SELECT q.newsletter_issue_id, q.subscriber_email, q.n_retries
+            FROM issue_delivery_queue q
+            JOIN newsletter_issues i ON i.newsletter_issue_id = q.newsletter_issue_id
+            WHERE q.subscriber_email = $1 AND q.execute_after <= now()
+            ORDER BY i.published_at
+            FOR UPDATE OF q
+            SKIP LOCKED
+            "#,
+            r.subscriber_email,
+        )
+        .fetch_all(&mut transaction)
+        .await?
+    };
+    Ok(Some((transaction, tasks)))
+}
+
+#[tracing::instrument(skip_all)]
+async fn delete_tasks(mut transaction: PgTransaction, tasks: &[Task]) -> Result<(), anyhow::Error> {
+    let issue_ids: Vec<Uuid> = tasks.iter().map(|t| t.newsletter_issue_id).collect();
+    sqlx::query!(
+        r#"
+        DELETE FROM issue_delivery_queue
+        WHERE
+            newsletter_issue_id = ANY($1) AND
+            subscriber_email = $2
+        "#,
+        &issue_ids,
+        tasks[0].subscriber_email
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+/// Keep track of what was sent to whom, for the subscriber's data export.
+#[tracing::instrument(skip(transaction, task))]
+async fn record_delivery(
+    transaction: &mut PgTransaction,
+    task: &Task,
+    subscriber_id: Uuid,
+    outcome: &str,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_log (
+            newsletter_issue_id,
+            subscriber_id,
+            outcome,
+            n_retries
+        )
+        VALUES ($1, $2, $3, $4)
+        "#,
+        task.newsletter_issue_id,
+        subscriber_id,
+        outcome,
+        task.n_retries
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+/// Put failed tasks back in the queue, backing off exponentially. The tasks
+/// of a digest are postponed together, so that they are retried as one.
+#[tracing::instrument(skip_all)]
+async fn postpone_tasks(
+    mut transaction: PgTransaction,
+    tasks: &[Task],
+    n_retries: i16,
+) -> Result<(), anyhow::Error> {
+    let delay = chrono::Duration::seconds(10 * 2i64.pow(n_retries as u32));
+    let issue_ids: Vec<Uuid> = tasks.iter().map(|t| t.newsletter_issue_id).collect();
+    sqlx::query!(
+        r#"
+        UPDATE issue_delivery_queue
+        SET
+            n_retries = $3,
+            execute_after = $4
+        WHERE
+            newsletter_issue_id = ANY($1) AND
+            subscriber_email = $2
+        "#,
+        &issue_ids,
+        tasks[0].subscriber_email,
+        n_retries + 1,
+        Utc::now() + delay
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+/// Look up the subscriber and their locale, provided they are still a
+/// confirmed member of the list the issue was published to.
+#[tracing::instrument(skip_all)]
+async fn get_confirmed_subscriber_id(
+    pool: &PgPool,
+    email: &SubscriberEmail,
+    newsletter_issue_id: Uuid,
+) -> Result<Option<(Uuid, Locale)>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT s.id, s.locale
+        FROM subscriptions s
+        JOIN list_memberships m ON m.subscriber_id = s.id
+        JOIN newsletter_issues i ON i.list_id = m.list_id
+        WHERE
+            s.email = $1 AND
+            i.newsletter_issue_id = $2 AND
+            m.status = 'confirmed'
+        "#,
+        email.as_ref(),
+        newsletter_issue_id
+    )
+    .fetch_optional(pool)
+    .await?;
+    // Only supported locales are ever stored; English is there for the
+    // locales that have since been dropped.
+    Ok(row.map(|r| (r.id, Locale::parse(&r.locale).unwrap_or(Locale::En))))
+}
+
+struct NewsletterIssue {
+    title: String,
+    text_content: String,
+    html_content: String,
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_issue(pool: &PgPool, issue_id: Uuid) -> Result<NewsletterIssue, anyhow::Error> {
+    let issue = sqlx::query_as!(
+        NewsletterIssue,
+        r#"
+        SELECT title, text_content, html_content
+        FROM newsletter_issues
+        WHERE
+            newsletter_issue_id = $1
+        "#,
+        issue_id
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(issue)
+}
+
+/// A delivery in progress when the shutdown is triggered is seen through;
+/// no other is started.
+async fn worker_loop(
+    pool: PgPool,
+    email_client: Arc<dyn EmailClient>,
+    templates: EmailTemplates,
+    links: SubscriberLinks,
+    shutdown: Shutdown,
+) -> Result<(), anyhow::Error> {
+    while !shutdown.is_triggered() {
+        match try_execute_task(&pool, email_client.as_ref(), &templates, &links).await {
+            Ok(ExecutionOutcome::EmptyQueue) => {
+                shutdown.sleep(Duration::from_secs(10)).await;
+            }
+            Err(_) => {
+                shutdown.sleep(Duration::from_secs(1)).await;
+            }
+            Ok(ExecutionOutcome::TaskCompleted) => {}
+        }
+    }
+    pool.close().await;
+    Ok(())
+}
+
+/// Emails sent and the connection pool are recorded in `metrics`, usually
+/// those of the `Application` running alongside.
+pub async fn run_worker_until_stopped(
+    configuration: Settings,
+    metrics: Arc<Metrics>,
+    shutdown: Shutdown,
+) -> Result<(), anyhow::Error> {
+    let connection_pool = get_connection_pool(&configuration.database);
+    metrics.register_pool("worker", connection_pool.clone());
+    let email_client = configuration.email_client.metered_client(metrics)?;
+    let templates = EmailTemplates::load(&configuration.email_templates)?;
+    let links = SubscriberLinks::new(
+        configuration.application.base_url,
+        configuration.application.hmac_secret,
+    );
+    worker_loop(connection_pool, email_client, templates, links, shutdown).await
+}
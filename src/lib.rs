@@ -1,6 +1,26 @@
+pub mod authentication;
+pub mod bot_protection;
+pub mod client_ip;
 pub mod configuration;
+pub mod consent;
 pub mod domain;
 pub mod email_client;
+pub mod email_templates;
+pub mod health;
+pub mod idempotency;
+pub mod issue_delivery_worker;
+pub mod lists;
+pub mod localization;
+pub mod metrics;
+pub mod openapi;
+pub mod rate_limit;
 pub mod routes;
+pub mod session_state;
+pub mod session_store;
+pub mod shutdown;
 pub mod startup;
+pub mod subscriber_data;
+pub mod subscriber_links;
 pub mod telemetry;
+pub mod token_cleanup_worker;
+pub mod utils;
@@ -0,0 +1,35 @@
+use crate::domain::ListSlug;
+use sqlx::{Postgres, Transaction};
+use uuid::Uuid;
+
+#[derive(thiserror::Error, Debug)]
+pub enum ListLookupError {
+    #[error("There is no list called {0}.")]
+    UnknownLists(String),
+    #[error("Failed to look up lists in the database.")]
+    DatabaseError(#[from] sqlx::Error),
+}
+
+/// Resolve list slugs to ids, failing if any of them does not exist.
+#[tracing::instrument(skip(transaction))]
+pub async fn get_list_ids(
+    transaction: &mut Transaction<'_, Postgres>,
+    slugs: &[ListSlug],
+) -> Result<Vec<Uuid>, ListLookupError> {
+    let slugs: Vec<String> = slugs.iter().map(|s| s.as_ref().to_owned()).collect();
+    let rows = sqlx::query!(
+        r#"SELECT list_id, slug FROM lists WHERE slug = ANY($1)"#,
+        &slugs[..]
+    )
+    .fetch_all(transaction)
+    .await?;
+    let unknown: Vec<&str> = slugs
+        .iter()
+        .filter(|slug| !rows.iter().any(|r| &r.slug == *slug))
+        .map(String::as_str)
+        .collect();
+    if !unknown.is_empty() {
+        return Err(ListLookupError::UnknownLists(unknown.join(", ")));
+    }
+    Ok(rows.into_iter().map(|r| r.list_id).collect())
+}
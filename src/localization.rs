@@ -0,0 +1,62 @@
+use crate::configuration::LocalizationSettings;
+use crate::domain::Locale;
+use actix_web::http::header::ACCEPT_LANGUAGE;
+use actix_web::HttpRequest;
+
+/// The locale to answer a request in: the one the client asked for
+/// explicitly, if we support it, then its `Accept-Language` preferences,
+/// then the configured default.
+pub fn negotiate_locale(
+    request: &HttpRequest,
+    requested: Option<&str>,
+    settings: &LocalizationSettings,
+) -> Locale {
+    requested
+        .and_then(|locale| Locale::parse(locale).ok())
+        .or_else(|| {
+            request
+                .headers()
+                .get(ACCEPT_LANGUAGE)
+                .and_then(|h| h.to_str().ok())
+                .and_then(Locale::from_accept_language)
+        })
+        .unwrap_or(settings.default_locale)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::negotiate_locale;
+    use crate::configuration::LocalizationSettings;
+    use crate::domain::Locale;
+    use actix_web::test::TestRequest;
+
+    fn settings() -> LocalizationSettings {
+        LocalizationSettings {
+            default_locale: Locale::En,
+        }
+    }
+
+    #[test]
+    fn an_explicit_choice_beats_accept_language() {
+        let request = TestRequest::default()
+            .insert_header(("Accept-Language", "en"))
+            .to_http_request();
+        assert_eq!(
+            negotiate_locale(&request, Some("fr"), &settings()),
+            Locale::Fr
+        );
+    }
+
+    #[test]
+    fn unsupported_choices_fall_back_to_accept_language_then_the_default() {
+        let request = TestRequest::default()
+            .insert_header(("Accept-Language", "de, fr;q=0.5"))
+            .to_http_request();
+        assert_eq!(
+            negotiate_locale(&request, Some("ja"), &settings()),
+            Locale::Fr
+        );
+        let request = TestRequest::default().to_http_request();
+        assert_eq!(negotiate_locale(&request, None, &settings()), Locale::En);
+    }
+}
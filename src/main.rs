@@ -1,14 +1,88 @@
+use anyhow::Context;
+use std::fmt::{Debug, Display};
+use tokio::task::{JoinError, JoinSet};
 use zero2prod::configuration::get_configuration;
+use zero2prod::issue_delivery_worker::run_worker_until_stopped;
+use zero2prod::shutdown::termination_signal;
 use zero2prod::startup::Application;
 use zero2prod::telemetry::{get_subscriber, init_subscriber};
+use zero2prod::token_cleanup_worker::run_cleanup_until_stopped;
 
 #[tokio::main]
-async fn main() -> std::io::Result<()> {
-    let subscriber = get_subscriber("zero2prod".into(), "info".into(), std::io::stdout);
+async fn main() -> anyhow::Result<()> {
+    let configuration = get_configuration().expect("Failed to read configuration.");
+    configuration.validate()?;
+    let tracer = configuration
+        .telemetry
+        .tracer()
+        .context("Failed to set up the OpenTelemetry exporter.")?;
+    let subscriber = get_subscriber("zero2prod".into(), "info".into(), std::io::stdout, tracer);
     init_subscriber(subscriber);
 
-    let configuration = get_configuration().expect("Failed to read configuration.");
-    let application = Application::build(configuration).await?;
-    application.run_until_stopped().await?;
+    // The listeners only stop once the pre-stop delay is over.
+    let grace_period = configuration.application.shutdown_pre_stop_delay()
+        + configuration.application.shutdown_grace_period();
+    let application = Application::build(configuration.clone()).await?;
+    let metrics = application.metrics();
+    let shutdown = application.shutdown();
+    let application_task = tokio::spawn(application.run_until_stopped());
+    let worker_task = tokio::spawn(run_worker_until_stopped(
+        configuration.clone(),
+        metrics,
+        shutdown.clone(),
+    ));
+    let cleanup_task = tokio::spawn(run_cleanup_until_stopped(configuration, shutdown.clone()));
+
+    let mut tasks = JoinSet::new();
+    tasks.spawn(async move { report_exit("API", application_task.await) });
+    tasks.spawn(async move { report_exit("Background worker", worker_task.await) });
+    tasks.spawn(async move { report_exit("Token cleanup", cleanup_task.await) });
+
+    // Wind everything down when asked to, or as soon as anything stops.
+    tokio::select! {
+        signal = termination_signal() => {
+            let signal = signal.context("Failed to listen for termination signals.")?;
+            tracing::info!("Received {}, shutting down", signal);
+        }
+        _ = tasks.join_next() => {}
+    };
+    shutdown.trigger();
+    let drained = tokio::time::timeout(grace_period, async {
+        while tasks.join_next().await.is_some() {}
+    })
+    .await;
+    if drained.is_err() {
+        tracing::warn!(
+            "Work still in progress after the {:?} grace period is abandoned",
+            grace_period
+        );
+    }
+
+    // Flush the spans that have not been exported yet.
+    tokio::task::spawn_blocking(opentelemetry::global::shutdown_tracer_provider).await?;
     Ok(())
 }
+
+fn report_exit(task_name: &str, outcome: Result<Result<(), impl Debug + Display>, JoinError>) {
+    match outcome {
+        Ok(Ok(())) => {
+            tracing::info!("{} has exited", task_name)
+        }
+        Ok(Err(e)) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "{} failed",
+                task_name
+            )
+        }
+        Err(e) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "{}' task failed to complete",
+                task_name
+            )
+        }
+    }
+}
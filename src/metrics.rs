@@ -0,0 +1,235 @@
+//! Prometheus metrics, scraped from `GET /metrics`.
+//!
+//! Every `Application` owns its registry rather than using the global one,
+//! so that several instances can live in one process.
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::web;
+use prometheus::core::Collector;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGaugeVec, Opts, Registry, TextEncoder,
+};
+use sqlx::PgPool;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// What became of a call to `POST /subscriptions`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubscriptionOutcome {
+    Created,
+    /// The address was already on file, pending or confirmed.
+    Duplicate,
+    ValidationFailure,
+    RateLimited,
+    /// Silently dropped by the bot checks.
+    Bot,
+}
+
+impl SubscriptionOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SubscriptionOutcome::Created => "created",
+            SubscriptionOutcome::Duplicate => "duplicate",
+            SubscriptionOutcome::ValidationFailure => "validation_failure",
+            SubscriptionOutcome::RateLimited => "rate_limited",
+            SubscriptionOutcome::Bot => "bot",
+        }
+    }
+}
+
+/// What became of a click on a confirmation link.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfirmationOutcome {
+    Confirmed,
+    AlreadyConfirmed,
+    UnknownToken,
+    ExpiredToken,
+}
+
+impl ConfirmationOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConfirmationOutcome::Confirmed => "confirmed",
+            ConfirmationOutcome::AlreadyConfirmed => "already_confirmed",
+            ConfirmationOutcome::UnknownToken => "unknown_token",
+            ConfirmationOutcome::ExpiredToken => "expired_token",
+        }
+    }
+}
+
+pub struct Metrics {
+    registry: Registry,
+    http_requests: IntCounterVec,
+    http_request_duration: HistogramVec,
+    subscriptions: IntCounterVec,
+    confirmations: IntCounterVec,
+    email_send_attempts: IntCounterVec,
+    email_send_failures: IntCounterVec,
+    email_send_duration: HistogramVec,
+    db_pool_connections: IntGaugeVec,
+    db_pool_idle_connections: IntGaugeVec,
+    /// Sampled on every scrape, labelled with their name.
+    pools: Mutex<Vec<(&'static str, PgPool)>>,
+}
+
+impl Metrics {
+    pub fn new() -> Result<Self, prometheus::Error> {
+        let registry = Registry::new();
+        let metrics = Self {
+            http_requests: IntCounterVec::new(
+                Opts::new("http_requests_total", "HTTP requests served."),
+                &["method", "route", "status"],
+            )?,
+            http_request_duration: HistogramVec::new(
+                HistogramOpts::new(
+                    "http_request_duration_seconds",
+                    "Time taken to serve HTTP requests.",
+                ),
+                &["method", "route"],
+            )?,
+            subscriptions: IntCounterVec::new(
+                Opts::new("subscriptions_total", "Sign-ups, by outcome."),
+                &["outcome"],
+            )?,
+            confirmations: IntCounterVec::new(
+                Opts::new(
+                    "subscription_confirmations_total",
+                    "Clicks on confirmation links, by outcome.",
+                ),
+                &["outcome"],
+            )?,
+            email_send_attempts: IntCounterVec::new(
+                Opts::new(
+                    "email_send_attempts_total",
+                    "Attempts at handing an email to the backend, retries included.",
+                ),
+                &["backend"],
+            )?,
+            email_send_failures: IntCounterVec::new(
+                Opts::new(
+                    "email_send_failures_total",
+                    "Attempts the backend failed, retried or not.",
+                ),
+                &["backend"],
+            )?,
+            email_send_duration: HistogramVec::new(
+                HistogramOpts::new(
+                    "email_send_duration_seconds",
+                    "Time taken by one attempt at sending an email.",
+                ),
+                &["backend"],
+            )?,
+            db_pool_connections: IntGaugeVec::new(
+                Opts::new(
+                    "db_pool_connections",
+                    "Connections to Postgres, idle or in use.",
+                ),
+                &["pool"],
+            )?,
+            db_pool_idle_connections: IntGaugeVec::new(
+                Opts::new(
+                    "db_pool_idle_connections",
+                    "Connections to Postgres waiting to be used.",
+                ),
+                &["pool"],
+            )?,
+            pools: Mutex::new(Vec::new()),
+            registry,
+        };
+        let collectors: Vec<Box<dyn Collector>> = vec![
+            Box::new(metrics.http_requests.clone()),
+            Box::new(metrics.http_request_duration.clone()),
+            Box::new(metrics.subscriptions.clone()),
+            Box::new(metrics.confirmations.clone()),
+            Box::new(metrics.email_send_attempts.clone()),
+            Box::new(metrics.email_send_failures.clone()),
+            Box::new(metrics.email_send_duration.clone()),
+            Box::new(metrics.db_pool_connections.clone()),
+            Box::new(metrics.db_pool_idle_connections.clone()),
+        ];
+        for collector in collectors {
+            metrics.registry.register(collector)?;
+        }
+        Ok(metrics)
+    }
+
+    /// `route` is the pattern the request matched, e.g.
+    /// `/subscriptions/confirm`, so that query strings and ids do not blow up
+    /// the number of series.
+    pub fn observe_request(&self, method: &str, route: &str, status: u16, seconds: f64) {
+        self.http_requests
+            .with_label_values(&[method, route, &status.to_string()])
+            .inc();
+        self.http_request_duration
+            .with_label_values(&[method, route])
+            .observe(seconds);
+    }
+
+    pub fn record_subscription(&self, outcome: SubscriptionOutcome) {
+        self.subscriptions
+            .with_label_values(&[outcome.as_str()])
+            .inc();
+    }
+
+    pub fn record_confirmation(&self, outcome: ConfirmationOutcome) {
+        self.confirmations
+            .with_label_values(&[outcome.as_str()])
+            .inc();
+    }
+
+    pub fn observe_email_send(&self, backend: &str, succeeded: bool, seconds: f64) {
+        self.email_send_attempts.with_label_values(&[backend]).inc();
+        if !succeeded {
+            self.email_send_failures.with_label_values(&[backend]).inc();
+        }
+        self.email_send_duration
+            .with_label_values(&[backend])
+            .observe(seconds);
+    }
+
+    /// Report the connections of `pool` under `name`, e.g. `worker`.
+    pub fn register_pool(&self, name: &'static str, pool: PgPool) {
+        self.pools.lock().unwrap().push((name, pool));
+    }
+
+    /// Everything in the text exposition format. Pool gauges are sampled
+    /// now rather than kept up to date.
+    pub fn render(&self) -> Result<String, prometheus::Error> {
+        for (name, pool) in self.pools.lock().unwrap().iter() {
+            self.db_pool_connections
+                .with_label_values(&[name])
+                .set(i64::from(pool.size()));
+            self.db_pool_idle_connections
+                .with_label_values(&[name])
+                .set(pool.num_idle() as i64);
+        }
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8(buffer).expect("The text format is UTF-8"))
+    }
+}
+
+/// Count and time every request, labelled with the route it matched.
+pub async fn track_requests(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let metrics = req.app_data::<web::Data<Metrics>>().cloned();
+    let method = req.method().to_string();
+    let start = Instant::now();
+    let outcome = next.call(req).await;
+    if let Some(metrics) = metrics {
+        let (route, status) = match &outcome {
+            Ok(response) => (response.request().match_pattern(), response.status()),
+            Err(e) => (None, e.as_response_error().status_code()),
+        };
+        metrics.observe_request(
+            &method,
+            route.as_deref().unwrap_or("unmatched"),
+            status.as_u16(),
+            start.elapsed().as_secs_f64(),
+        );
+    }
+    outcome
+}
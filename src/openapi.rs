@@ -0,0 +1,88 @@
+//! The OpenAPI description of the HTTP API, generated from the handlers and
+//! their payload types.
+//!
+//! The admin dashboard and the login pages are HTML forms for browsers and
+//! are left out.
+use crate::routes;
+use utoipa::openapi::security::{Http, HttpAuthScheme, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        routes::health_check,
+        routes::liveness,
+        routes::readiness,
+        routes::subscribe,
+        routes::sign_up_challenge,
+        routes::confirm,
+        routes::unsubscribe_form,
+        routes::unsubscribe,
+        routes::preferences_form,
+        routes::update_preferences,
+        routes::export_own_data,
+        routes::erase_own_data,
+        routes::publish_newsletter,
+    ),
+    info(description = "Sign-ups, subscription management and newsletter publishing."),
+    modifiers(&BasicAuth, &NoLicense),
+    tags(
+        (name = "health"),
+        (name = "subscriptions", description = "Signing up and managing a subscription."),
+        (name = "newsletters", description = "Publishing issues, for authors."),
+    )
+)]
+pub struct ApiDoc;
+
+struct BasicAuth;
+
+impl Modify for BasicAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "basic_auth",
+            SecurityScheme::Http(Http::new(HttpAuthScheme::Basic)),
+        );
+    }
+}
+
+/// The crate has no `license` for utoipa to fill in: drop the empty one.
+struct NoLicense;
+
+impl Modify for NoLicense {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        if openapi
+            .info
+            .license
+            .as_ref()
+            .is_some_and(|l| l.name.is_empty())
+        {
+            openapi.info.license = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ApiDoc;
+    use std::path::PathBuf;
+    use utoipa::OpenApi;
+
+    /// Set `UPDATE_OPENAPI` to rewrite `openapi.json` instead.
+    #[test]
+    fn the_committed_spec_matches_the_generated_one() {
+        let generated = ApiDoc::openapi().to_pretty_json().unwrap() + "\n";
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("openapi.json");
+        if std::env::var_os("UPDATE_OPENAPI").is_some() {
+            std::fs::write(&path, &generated).unwrap();
+        }
+
+        let committed = std::fs::read_to_string(&path).unwrap_or_default();
+
+        assert!(
+            committed == generated,
+            "openapi.json is out of date: run `UPDATE_OPENAPI=1 cargo test --lib openapi` \
+             and commit the result."
+        );
+    }
+}
@@ -0,0 +1,44 @@
+use super::RateLimitStore;
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Counters kept in the memory of this instance only.
+#[derive(Default)]
+pub struct InMemoryRateLimitStore {
+    counters: Mutex<HashMap<String, Counter>>,
+}
+
+struct Counter {
+    window_start: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+    hits: u32,
+}
+
+#[async_trait::async_trait]
+impl RateLimitStore for InMemoryRateLimitStore {
+    async fn hit(
+        &self,
+        key: &str,
+        window_start: DateTime<Utc>,
+        expires_at: DateTime<Utc>,
+    ) -> Result<u32, anyhow::Error> {
+        let mut counters = self.counters.lock().unwrap();
+        // Windows that ended before this one started are over for good.
+        counters.retain(|_, counter| counter.expires_at > window_start);
+        let counter = counters.entry(key.to_owned()).or_insert(Counter {
+            window_start,
+            expires_at,
+            hits: 0,
+        });
+        if counter.window_start != window_start {
+            *counter = Counter {
+                window_start,
+                expires_at,
+                hits: 0,
+            };
+        }
+        counter.hits += 1;
+        Ok(counter.hits)
+    }
+}
@@ -0,0 +1,171 @@
+mod memory;
+mod postgres;
+
+pub use memory::InMemoryRateLimitStore;
+pub use postgres::{delete_expired_counters, PostgresRateLimitStore};
+
+use chrono::{DateTime, TimeZone, Utc};
+use sha2::{Digest, Sha256};
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Where rate limit counters are kept.
+///
+/// Every instance has to share the same store for the limits to hold
+/// across a deployment: the in-memory one is meant for single-instance
+/// setups and tests.
+#[async_trait::async_trait]
+pub trait RateLimitStore: Send + Sync {
+    /// Count a hit against `key` in the window starting at `window_start`
+    /// and return the number of hits in that window so far, this one
+    /// included. Counters are only needed until `expires_at`.
+    async fn hit(
+        &self,
+        key: &str,
+        window_start: DateTime<Utc>,
+        expires_at: DateTime<Utc>,
+    ) -> Result<u32, anyhow::Error>;
+}
+
+/// At most `max_requests` per fixed window of `window`.
+#[derive(Debug, Clone, Copy)]
+pub struct Limit {
+    pub max_requests: u32,
+    pub window: Duration,
+}
+
+pub enum RateLimitDecision {
+    Allowed,
+    Limited { retry_after: Duration },
+}
+
+/// Throttles sign-ups by client address and by target email address.
+pub struct RateLimiter {
+    store: Arc<dyn RateLimitStore>,
+    per_ip: Limit,
+    per_email: Limit,
+}
+
+impl RateLimiter {
+    pub fn new(store: Arc<dyn RateLimitStore>, per_ip: Limit, per_email: Limit) -> Self {
+        Self {
+            store,
+            per_ip,
+            per_email,
+        }
+    }
+
+    pub async fn check_ip(&self, ip: IpAddr) -> Result<RateLimitDecision, anyhow::Error> {
+        self.check(&format!("subscribe:ip:{}", ip), self.per_ip, Utc::now())
+            .await
+    }
+
+    /// The address is hashed: we do not want the counters to be another
+    /// copy of our subscribers' email addresses.
+    pub async fn check_email(&self, email: &str) -> Result<RateLimitDecision, anyhow::Error> {
+        let digest = Sha256::digest(email.to_lowercase().as_bytes());
+        self.check(
+            &format!("subscribe:email:{}", hex::encode(digest)),
+            self.per_email,
+            Utc::now(),
+        )
+        .await
+    }
+
+    async fn check(
+        &self,
+        key: &str,
+        limit: Limit,
+        now: DateTime<Utc>,
+    ) -> Result<RateLimitDecision, anyhow::Error> {
+        let (window_start, window_end) = window(now, limit.window);
+        let hits = self.store.hit(key, window_start, window_end).await?;
+        if hits <= limit.max_requests {
+            return Ok(RateLimitDecision::Allowed);
+        }
+        // Round up: retrying a fraction of a second early would fail again.
+        let retry_after = (window_end - now).num_milliseconds().max(0) as u64;
+        Ok(RateLimitDecision::Limited {
+            retry_after: Duration::from_secs(retry_after.div_ceil(1000)),
+        })
+    }
+}
+
+/// The fixed window `now` falls in. Windows are aligned on the Unix epoch so
+/// that every instance agrees on them.
+fn window(now: DateTime<Utc>, length: Duration) -> (DateTime<Utc>, DateTime<Utc>) {
+    let length = length.as_secs().max(1) as i64;
+    let start = now.timestamp() - now.timestamp().rem_euclid(length);
+    (
+        Utc.timestamp_opt(start, 0).unwrap(),
+        Utc.timestamp_opt(start + length, 0).unwrap(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{window, InMemoryRateLimitStore, Limit, RateLimitDecision, RateLimiter};
+    use chrono::{TimeZone, Utc};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn limiter(max_requests: u32) -> RateLimiter {
+        let limit = Limit {
+            max_requests,
+            window: Duration::from_secs(60),
+        };
+        RateLimiter::new(Arc::new(InMemoryRateLimitStore::default()), limit, limit)
+    }
+
+    #[test]
+    fn windows_are_aligned_on_their_length() {
+        let now = Utc.with_ymd_and_hms(2022, 6, 11, 14, 30, 27).unwrap();
+        let (start, end) = window(now, Duration::from_secs(3600));
+        assert_eq!(start, Utc.with_ymd_and_hms(2022, 6, 11, 14, 0, 0).unwrap());
+        assert_eq!(end, Utc.with_ymd_and_hms(2022, 6, 11, 15, 0, 0).unwrap());
+    }
+
+    #[tokio::test]
+    async fn requests_over_the_limit_are_told_when_to_retry() {
+        let limiter = limiter(2);
+        let now = Utc.with_ymd_and_hms(2022, 6, 11, 14, 30, 27).unwrap();
+
+        for _ in 0..2 {
+            let decision = limiter.check("key", limiter.per_ip, now).await.unwrap();
+            assert!(matches!(decision, RateLimitDecision::Allowed));
+        }
+        let decision = limiter.check("key", limiter.per_ip, now).await.unwrap();
+
+        match decision {
+            RateLimitDecision::Limited { retry_after } => {
+                assert_eq!(retry_after, Duration::from_secs(33))
+            }
+            RateLimitDecision::Allowed => panic!("The third request should be limited"),
+        }
+    }
+
+    #[tokio::test]
+    async fn counters_start_over_in_the_next_window() {
+        let limiter = limiter(1);
+        let now = Utc.with_ymd_and_hms(2022, 6, 11, 14, 30, 27).unwrap();
+
+        limiter.check("key", limiter.per_ip, now).await.unwrap();
+        let decision = limiter
+            .check("key", limiter.per_ip, now + chrono::Duration::seconds(60))
+            .await
+            .unwrap();
+
+        assert!(matches!(decision, RateLimitDecision::Allowed));
+    }
+
+    #[tokio::test]
+    async fn email_addresses_are_counted_regardless_of_case() {
+        let limiter = limiter(1);
+
+        limiter.check_email("ursula@example.com").await.unwrap();
+        let decision = limiter.check_email("Ursula@Example.com").await.unwrap();
+
+        assert!(matches!(decision, RateLimitDecision::Limited { .. }));
+    }
+}
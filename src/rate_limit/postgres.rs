@@ -0,0 +1,58 @@
+use super::RateLimitStore;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+
+/// Counters kept in Postgres, shared by every instance using the database.
+pub struct PostgresRateLimitStore {
+    pool: PgPool,
+}
+
+impl PostgresRateLimitStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl RateLimitStore for PostgresRateLimitStore {
+    #[tracing::instrument(name = "Count a rate-limited request", skip(self))]
+    async fn hit(
+        &self,
+        key: &str,
+        window_start: DateTime<Utc>,
+        expires_at: DateTime<Utc>,
+    ) -> Result<u32, anyhow::Error> {
+        let row = sqlx::query!(
+            r#"
+            INSERT INTO rate_limit_counters (key, window_start, expires_at, hits)
+            VALUES ($1, $2, $3, 1)
+            ON CONFLICT (key) DO UPDATE
+            SET
+                hits = CASE
+                    WHEN rate_limit_counters.window_start = EXCLUDED.window_start
+                    THEN rate_limit_counters.hits + 1
+                    ELSE 1
+                END,
+                window_start = EXCLUDED.window_start,
+                expires_at = EXCLUDED.expires_at
+            RETURNING hits
+            "#,
+            key,
+            window_start,
+            expires_at,
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(row.hits as u32)
+    }
+}
+
+/// Counters of past windows are never read again.
+#[tracing::instrument(skip(pool), err)]
+pub async fn delete_expired_counters(pool: &PgPool) -> Result<u64, anyhow::Error> {
+    let n_deleted = sqlx::query!("DELETE FROM rate_limit_counters WHERE expires_at < now()")
+        .execute(pool)
+        .await?
+        .rows_affected();
+    Ok(n_deleted)
+}
@@ -0,0 +1,143 @@
+use crate::authentication::UserId;
+use crate::subscriber_data::{erase_subscriber, export_subscriber_data, find_subscriber_by_email};
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+#[derive(serde::Deserialize)]
+pub struct SubscriberLookup {
+    email: String,
+}
+
+/// Show what we know about a subscriber, including how they opted in.
+#[tracing::instrument(name = "Show subscriber detail", skip_all, fields(user_id = %*user_id))]
+pub async fn subscriber_detail(
+    lookup: web::Query<SubscriberLookup>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let export = match find_subscriber_by_email(&pool, &lookup.email)
+        .await
+        .map_err(e500)?
+    {
+        Some(subscriber_id) => export_subscriber_data(&pool, subscriber_id)
+            .await
+            .map_err(e500)?,
+        None => None,
+    };
+    let export = match export {
+        Some(export) => export,
+        None => {
+            return Ok(HttpResponse::NotFound()
+                .content_type(ContentType::html())
+                .body(format!(
+                    "<p>There is no subscriber with the address {}.</p>",
+                    encode_minimal(&lookup.email)
+                )))
+        }
+    };
+
+    let mut lists_html = String::new();
+    for membership in &export.list_memberships {
+        writeln!(
+            lists_html,
+            "<li>{} ({})</li>",
+            encode_minimal(&membership.list),
+            encode_minimal(&membership.status)
+        )
+        .unwrap();
+    }
+    let mut consent_html = String::new();
+    for record in &export.consent_records {
+        writeln!(
+            consent_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            encode_minimal(&record.event),
+            record.recorded_at.to_rfc3339(),
+            encode_minimal(record.ip_address.as_deref().unwrap_or("-")),
+            encode_minimal(record.user_agent.as_deref().unwrap_or("-")),
+            encode_minimal(&record.source),
+            encode_minimal(&record.privacy_policy_version)
+        )
+        .unwrap();
+    }
+    let subscriber = &export.subscriber;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Subscriber detail</title>
+</head>
+<body>
+    <h1>{email}</h1>
+    <p>Name: {name}</p>
+    <p>Status: {status}</p>
+    <p>Subscribed at: {subscribed_at}</p>
+    <h2>Lists</h2>
+    <ul>
+        {lists_html}
+    </ul>
+    <h2>Consent records</h2>
+    <table>
+        <tr><th>Event</th><th>Recorded at</th><th>IP address</th><th>User agent</th><th>Source</th><th>Privacy policy</th></tr>
+        {consent_html}
+    </table>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+            email = encode_minimal(&subscriber.email),
+            name = encode_minimal(&subscriber.name),
+            status = encode_minimal(&subscriber.status),
+            subscribed_at = subscriber.subscribed_at.to_rfc3339(),
+        )))
+}
+
+/// Answer a subject-access request: everything stored about an address.
+#[tracing::instrument(name = "Export subscriber data", skip_all, fields(user_id = %*user_id))]
+pub async fn export_subscriber(
+    lookup: web::Query<SubscriberLookup>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = match find_subscriber_by_email(&pool, &lookup.email)
+        .await
+        .map_err(e500)?
+    {
+        Some(subscriber_id) => subscriber_id,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    match export_subscriber_data(&pool, subscriber_id)
+        .await
+        .map_err(e500)?
+    {
+        Some(export) => Ok(HttpResponse::Ok().json(export)),
+        None => Ok(HttpResponse::NotFound().finish()),
+    }
+}
+
+/// Answer an erasure request for an address.
+#[tracing::instrument(name = "Erase subscriber data", skip_all, fields(user_id = %*user_id))]
+pub async fn erase_subscriber_data(
+    lookup: web::Json<SubscriberLookup>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = match find_subscriber_by_email(&pool, &lookup.email)
+        .await
+        .map_err(e500)?
+    {
+        Some(subscriber_id) => subscriber_id,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    if erase_subscriber(&pool, subscriber_id).await.map_err(e500)? {
+        Ok(HttpResponse::Ok().finish())
+    } else {
+        Ok(HttpResponse::NotFound().finish())
+    }
+}
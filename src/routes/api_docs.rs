@@ -0,0 +1,25 @@
+use crate::openapi::ApiDoc;
+use actix_web::http::header::ContentType;
+use actix_web::HttpResponse;
+use utoipa::OpenApi;
+
+pub async fn openapi_json() -> HttpResponse {
+    HttpResponse::Ok().json(ApiDoc::openapi())
+}
+
+/// Browsable documentation for `/openapi.json`, rendered by Redoc.
+pub async fn api_docs() -> HttpResponse {
+    HttpResponse::Ok().content_type(ContentType::html()).body(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>API documentation</title>
+</head>
+<body>
+    <redoc spec-url="/openapi.json"></redoc>
+    <script src="https://cdn.redoc.ly/redoc/v2.0.0/bundles/redoc.standalone.js"></script>
+</body>
+</html>"#,
+    )
+}
@@ -1,5 +1,48 @@
-use actix_web::HttpResponse;
+use crate::health::{Readiness, ReadinessProbe, ReadinessStatus};
+use actix_web::http::header::{CacheControl, CacheDirective};
+use actix_web::{web, HttpResponse};
 
+#[utoipa::path(
+    get,
+    path = "/health_check",
+    tag = "health",
+    responses((status = 200, description = "The application is up."))
+)]
 pub async fn health_check() -> HttpResponse {
     HttpResponse::Ok().finish()
 }
+
+/// The process is up and serving requests. Nothing else is checked, so that
+/// an outage of a dependency does not get every instance restarted.
+#[utoipa::path(
+    get,
+    path = "/health/live",
+    tag = "health",
+    responses((status = 200, description = "The process is up."))
+)]
+pub async fn liveness() -> HttpResponse {
+    HttpResponse::Ok().finish()
+}
+
+/// Whether the application can do its job: Postgres answers, its schema is
+/// up to date and, if configured, the email provider is reachable.
+#[utoipa::path(
+    get,
+    path = "/health/ready",
+    tag = "health",
+    responses(
+        (status = 200, description = "Ready to serve traffic.", body = Readiness),
+        (status = 503, description = "At least one dependency is down.", body = Readiness),
+    )
+)]
+#[tracing::instrument(name = "Check readiness", skip_all)]
+pub async fn readiness(probe: web::Data<ReadinessProbe>) -> HttpResponse {
+    let readiness = probe.check().await;
+    let mut response = match readiness.status {
+        ReadinessStatus::Ready => HttpResponse::Ok(),
+        ReadinessStatus::NotReady => HttpResponse::ServiceUnavailable(),
+    };
+    response
+        .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+        .json(readiness)
+}
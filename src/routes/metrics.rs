@@ -0,0 +1,10 @@
+use crate::metrics::Metrics;
+use crate::utils::e500;
+use actix_web::{web, HttpResponse};
+
+pub async fn scrape_metrics(metrics: web::Data<Metrics>) -> Result<HttpResponse, actix_web::Error> {
+    let body = metrics.render().map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(prometheus::TEXT_FORMAT)
+        .body(body))
+}
@@ -0,0 +1,228 @@
+use crate::authentication::BasicAuthUser;
+use crate::configuration::IdempotencySettings;
+use crate::domain::{DigestFrequency, ListSlug};
+use crate::idempotency::{save_response, try_processing, IdempotencyKey, NextAction};
+use crate::lists::{get_list_ids, ListLookupError};
+use crate::routes::error_chain_fmt;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::{PgPool, Postgres, Transaction};
+use std::convert::TryInto;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub struct BodyData {
+    title: String,
+    content: Content,
+    /// Slug of the list to deliver the issue to. Defaults to the main list.
+    list: Option<String>,
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub struct Content {
+    html: String,
+    text: String,
+}
+
+#[derive(thiserror::Error)]
+pub enum PublishError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for PublishError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for PublishError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            PublishError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            PublishError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl From<ListLookupError> for PublishError {
+    fn from(e: ListLookupError) -> Self {
+        match e {
+            ListLookupError::UnknownLists(_) => PublishError::ValidationError(e.to_string()),
+            ListLookupError::DatabaseError(_) => PublishError::UnexpectedError(e.into()),
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/newsletters",
+    tag = "newsletters",
+    request_body = BodyData,
+    params(
+        ("Idempotency-Key" = Option<String>, Header, description = "Retries with the same key get the first response back instead of publishing twice."),
+    ),
+    security(("basic_auth" = [])),
+    responses(
+        (status = 200, description = "The issue is queued for delivery."),
+        (status = 400, description = "The issue is invalid.", body = String, content_type = "text/plain"),
+        (status = 401, description = "Missing or wrong credentials."),
+    )
+)]
+#[tracing::instrument(
+    name = "Publish a newsletter issue",
+    skip(body, pool, idempotency, user, request),
+    fields(user_id = %user.user_id())
+)]
+pub async fn publish_newsletter(
+    user: BasicAuthUser,
+    body: web::Json<BodyData>,
+    pool: web::Data<PgPool>,
+    idempotency: web::Data<IdempotencySettings>,
+    request: HttpRequest,
+) -> Result<HttpResponse, PublishError> {
+    let user_id = user.user_id();
+    let list = match &body.list {
+        Some(list) => ListSlug::parse(list.clone())
+            .map_err(|e| PublishError::ValidationError(e.to_string()))?,
+        None => ListSlug::default(),
+    };
+    // Requests carrying an idempotency key we have already seen get the
+    // saved response back instead of enqueuing a second delivery.
+    let (mut transaction, idempotency_key) = match idempotency_key(&request)? {
+        Some(idempotency_key) => {
+            match try_processing(&pool, &idempotency_key, user_id, idempotency.ttl()).await? {
+                NextAction::StartProcessing(transaction) => (transaction, Some(idempotency_key)),
+                NextAction::ReturnSavedResponse(saved_response) => return Ok(saved_response),
+            }
+        }
+        None => (
+            pool.begin()
+                .await
+                .context("Failed to acquire a Postgres connection from the pool")?,
+            None,
+        ),
+    };
+
+    let list_id = get_list_ids(&mut transaction, &[list]).await?[0];
+    let issue_id = insert_newsletter_issue(
+        &mut transaction,
+        list_id,
+        &body.title,
+        &body.content.text,
+        &body.content.html,
+    )
+    .await
+    .context("Failed to store newsletter issue details")?;
+    enqueue_delivery_tasks(&mut transaction, issue_id)
+        .await
+        .context("Failed to enqueue delivery tasks")?;
+
+    let response = HttpResponse::Ok().finish();
+    let response = match idempotency_key {
+        Some(idempotency_key) => {
+            save_response(transaction, &idempotency_key, user_id, response).await?
+        }
+        None => {
+            transaction
+                .commit()
+                .await
+                .context("Failed to commit SQL transaction to store a newsletter issue.")?;
+            response
+        }
+    };
+    Ok(response)
+}
+
+/// Read the optional `Idempotency-Key` header.
+fn idempotency_key(request: &HttpRequest) -> Result<Option<IdempotencyKey>, PublishError> {
+    request
+        .headers()
+        .get("Idempotency-Key")
+        .map(|value| {
+            value
+                .to_str()
+                .map_err(|_| {
+                    PublishError::ValidationError(
+                        "The 'Idempotency-Key' header was not a valid UTF8 string.".into(),
+                    )
+                })?
+                .to_owned()
+                .try_into()
+                .map_err(|e: anyhow::Error| PublishError::ValidationError(e.to_string()))
+        })
+        .transpose()
+}
+
+#[tracing::instrument(skip_all)]
+async fn insert_newsletter_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    list_id: Uuid,
+    title: &str,
+    text_content: &str,
+    html_content: &str,
+) -> Result<Uuid, sqlx::Error> {
+    let newsletter_issue_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues (
+            newsletter_issue_id,
+            list_id,
+            title,
+            text_content,
+            html_content,
+            published_at
+        )
+        VALUES ($1, $2, $3, $4, $5, now())
+        "#,
+        newsletter_issue_id,
+        list_id,
+        title,
+        text_content,
+        html_content
+    )
+    .execute(transaction)
+    .await?;
+    Ok(newsletter_issue_id)
+}
+
+/// Queue one delivery task for every confirmed member of the issue's list,
+/// due when their digest goes out.
+#[tracing::instrument(skip_all)]
+async fn enqueue_delivery_tasks(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    let now = Utc::now();
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_queue (
+            newsletter_issue_id,
+            subscriber_email,
+            execute_after
+        )
+        SELECT
+            i.newsletter_issue_id,
+            s.email,
+            CASE s.digest_frequency
+                WHEN 'daily' THEN $2
+                WHEN 'weekly' THEN $3
+                ELSE now()
+            END
+        FROM newsletter_issues i
+        JOIN list_memberships m ON m.list_id = i.list_id
+        JOIN subscriptions s ON s.id = m.subscriber_id
+        WHERE i.newsletter_issue_id = $1 AND m.status = 'confirmed'
+        "#,
+        newsletter_issue_id,
+        DigestFrequency::Daily.next_delivery(now),
+        DigestFrequency::Weekly.next_delivery(now),
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
@@ -1,36 +1,134 @@
-use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName};
+use crate::bot_protection::{BotCheck, BotProtection};
+use crate::client_ip::TrustedProxies;
+use crate::configuration::{ConsentSettings, LocalizationSettings, SubscriptionTokenSettings};
+use crate::consent::{record_consent, ConsentContext, ConsentEvent};
+use crate::domain::{
+    InvalidInput, ListSlug, Locale, NewSubscriber, SubscriberEmail, SubscriberName,
+};
 use crate::email_client::EmailClient;
+use crate::email_templates::{AlreadySubscribedEmail, ConfirmationEmail, EmailTemplates};
+use crate::lists::{get_list_ids, ListLookupError};
+use crate::localization::negotiate_locale;
+use crate::metrics::{Metrics, SubscriptionOutcome};
+use crate::rate_limit::{RateLimitDecision, RateLimiter};
 use crate::startup::ApplicationBaseUrl;
+use crate::subscriber_links::SubscriberLinks;
+use actix_web::http::header::{ContentType, RETRY_AFTER};
 use actix_web::http::StatusCode;
-use actix_web::{web, HttpResponse, ResponseError};
+use actix_web::{web, Either, HttpRequest, HttpResponse, ResponseError};
 use anyhow::Context;
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
 use sqlx::{PgPool, Postgres, Transaction};
-use std::convert::{TryFrom, TryInto};
 use uuid::Uuid;
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, utoipa::ToSchema)]
 pub struct FormData {
+    // Missing fields are reported along with the invalid ones, not as a
+    // body that does not deserialize.
+    #[serde(default)]
     email: String,
+    #[serde(default)]
     name: String,
+    /// Comma-separated slugs of the lists to join. Defaults to the main list.
+    lists: Option<String>,
+    /// Which form the sign-up came from, kept in the consent record.
+    source: Option<String>,
+    /// The language to write to the subscriber in. Defaults to the
+    /// preferences sent in `Accept-Language`.
+    locale: Option<String>,
+    /// Honeypot: hidden from humans by the form, so only bots fill it in.
+    website: Option<String>,
+    /// A challenge from `GET /subscriptions/challenge`, and its solution.
+    challenge: Option<String>,
+    nonce: Option<String>,
 }
 
-impl TryFrom<FormData> for NewSubscriber {
-    type Error = String;
+/// One problem with one field of a sign-up.
+#[derive(Debug, serde::Serialize, utoipa::ToSchema)]
+pub struct FieldError {
+    pub field: &'static str,
+    /// Stable, for clients to match on; `message` is meant for people.
+    pub code: &'static str,
+    pub message: String,
+}
+
+/// The body of a 400 for a sign-up sent as JSON.
+#[derive(serde::Serialize, utoipa::ToSchema)]
+pub struct FieldErrors<'a> {
+    pub errors: &'a [FieldError],
+}
+
+impl FieldError {
+    fn from_input(e: InvalidInput, locale: Locale) -> Self {
+        Self {
+            field: e.field(),
+            code: e.code(),
+            message: e.localize(locale),
+        }
+    }
+}
 
-    fn try_from(value: FormData) -> Result<Self, Self::Error> {
-        let name = SubscriberName::parse(value.name)?;
-        let email = SubscriberEmail::parse(value.email)?;
-        Ok(Self { email, name })
+impl NewSubscriber {
+    /// Validate every field of a sign-up, reporting all of their problems
+    /// at once. `locale` is the one negotiated for the subscriber.
+    fn parse(form: FormData, locale: Locale) -> Result<Self, Vec<InvalidInput>> {
+        let mut errors = Vec::new();
+        let name = SubscriberName::parse(form.name)
+            .map_err(|e| errors.extend(e))
+            .ok();
+        let email = SubscriberEmail::parse(form.email)
+            .map_err(|e| errors.push(e))
+            .ok();
+        let lists = parse_list_slugs(form.lists.as_deref().unwrap_or_default())
+            .map_err(|e| errors.push(e))
+            .ok();
+        let source = parse_source(form.source).map_err(|e| errors.push(e)).ok();
+        match (name, email, lists, source) {
+            (Some(name), Some(email), Some(lists), Some(source)) => Ok(Self {
+                email,
+                name,
+                lists,
+                locale,
+                source,
+            }),
+            _ => Err(errors),
+        }
     }
 }
 
+fn parse_source(source: Option<String>) -> Result<String, InvalidInput> {
+    match source {
+        None => Ok("subscription_form".into()),
+        Some(source) if !source.trim().is_empty() && source.chars().count() <= 64 => {
+            Ok(source.trim().to_owned())
+        }
+        Some(source) => Err(InvalidInput::Source(source)),
+    }
+}
+
+fn parse_list_slugs(s: &str) -> Result<Vec<ListSlug>, InvalidInput> {
+    let mut lists = Vec::new();
+    for slug in s.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        let slug = ListSlug::parse(slug.to_owned())?;
+        if !lists.contains(&slug) {
+            lists.push(slug);
+        }
+    }
+    if lists.is_empty() {
+        lists.push(ListSlug::default());
+    }
+    Ok(lists)
+}
+
 #[derive(thiserror::Error)]
 pub enum SubscribeError {
-    #[error("{0}")]
-    ValidationError(String),
+    /// `json` is set when the sign-up was sent as JSON, to answer in kind.
+    #[error("{}", field_messages(.errors))]
+    ValidationError { errors: Vec<FieldError>, json: bool },
+    #[error("Too many sign-up attempts. Please try again later.")]
+    TooManyRequests { retry_after: std::time::Duration },
     #[error(transparent)]
     UnexpectedError(#[from] anyhow::Error),
 }
@@ -44,51 +142,424 @@ impl std::fmt::Debug for SubscribeError {
 impl ResponseError for SubscribeError {
     fn status_code(&self) -> StatusCode {
         match self {
-            SubscribeError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            SubscribeError::ValidationError { .. } => StatusCode::BAD_REQUEST,
+            SubscribeError::TooManyRequests { .. } => StatusCode::TOO_MANY_REQUESTS,
             SubscribeError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code());
+        if let SubscribeError::ValidationError { errors, json: true } = self {
+            return response.json(FieldErrors { errors });
+        }
+        if let SubscribeError::TooManyRequests { retry_after } = self {
+            response.insert_header((RETRY_AFTER, retry_after.as_secs().to_string()));
+        }
+        response
+            .content_type(ContentType::plaintext())
+            .body(self.to_string())
+    }
+}
+
+fn enforce(metrics: &Metrics, decision: RateLimitDecision) -> Result<(), SubscribeError> {
+    match decision {
+        RateLimitDecision::Allowed => Ok(()),
+        RateLimitDecision::Limited { retry_after } => {
+            metrics.record_subscription(SubscriptionOutcome::RateLimited);
+            Err(SubscribeError::TooManyRequests { retry_after })
+        }
+    }
 }
 
+/// One per line, for clients that did not ask for JSON.
+fn field_messages(errors: &[FieldError]) -> String {
+    errors
+        .iter()
+        .map(|e| e.message.as_str())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Unknown lists can only be spotted in the database, so they are reported
+/// once every other field is valid.
+fn lookup_error(
+    metrics: &Metrics,
+    e: ListLookupError,
+    locale: Locale,
+    json: bool,
+) -> SubscribeError {
+    match e {
+        ListLookupError::UnknownLists(lists) => {
+            metrics.record_subscription(SubscriptionOutcome::ValidationFailure);
+            SubscribeError::ValidationError {
+                errors: vec![FieldError::from_input(
+                    InvalidInput::UnknownLists(lists),
+                    locale,
+                )],
+                json,
+            }
+        }
+        ListLookupError::DatabaseError(_) => SubscribeError::UnexpectedError(e.into()),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/subscriptions",
+    tag = "subscriptions",
+    request_body(content(
+        (FormData = "application/json"),
+        (FormData = "application/x-www-form-urlencoded"),
+    )),
+    responses(
+        (status = 200, description = "An email is on its way: a confirmation link, or a notice if the address has already confirmed every requested list. Sign-ups failing the bot checks get the same answer."),
+        (status = 400, description = "Some fields are invalid: every problem is listed, as JSON for JSON requests and one per line otherwise.", content(
+            (FieldErrors = "application/json"),
+            (String = "text/plain"),
+        )),
+        (status = 429, description = "Too many sign-ups from this client or for this address.", body = String, content_type = "text/plain",
+            headers(("Retry-After" = u64, description = "Seconds to wait before trying again."))),
+    )
+)]
 #[tracing::instrument(
     name = "Adding a new subscriber",
-    skip(form, pool, email_client, base_url),
+    skip(
+        body,
+        pool,
+        email_client,
+        templates,
+        base_url,
+        token_settings,
+        links,
+        request,
+        trusted_proxies,
+        rate_limiter,
+        bot_protection,
+        metrics,
+        consent,
+        localization
+    ),
     fields(
-        subscriber_email = %form.email,
-        subscriber_name = %form.name
+        subscriber_email = tracing::field::Empty,
+        subscriber_name = tracing::field::Empty,
+        bot_check = tracing::field::Empty
     )
 )]
+#[allow(clippy::too_many_arguments)]
 pub async fn subscribe(
-    form: web::Form<FormData>,
+    body: Either<web::Json<FormData>, web::Form<FormData>>,
     pool: web::Data<PgPool>,
-    email_client: web::Data<EmailClient>,
+    email_client: web::Data<dyn EmailClient>,
+    templates: web::Data<EmailTemplates>,
     base_url: web::Data<ApplicationBaseUrl>,
+    token_settings: web::Data<SubscriptionTokenSettings>,
+    links: web::Data<SubscriberLinks>,
+    request: HttpRequest,
+    trusted_proxies: web::Data<TrustedProxies>,
+    rate_limiter: web::Data<RateLimiter>,
+    bot_protection: web::Data<BotProtection>,
+    metrics: web::Data<Metrics>,
+    consent: web::Data<ConsentSettings>,
+    localization: web::Data<LocalizationSettings>,
 ) -> Result<HttpResponse, SubscribeError> {
-    let new_subscriber = form.0.try_into().map_err(SubscribeError::ValidationError)?;
+    let client_ip = trusted_proxies.client_ip(&request);
+    if let Some(ip) = client_ip {
+        enforce(
+            &metrics,
+            rate_limiter
+                .check_ip(ip)
+                .await
+                .context("Failed to check the rate limit of a client.")?,
+        )?;
+    }
+    let (form, json) = match body {
+        Either::Left(form) => (form.into_inner(), true),
+        Either::Right(form) => (form.into_inner(), false),
+    };
+    let span = tracing::Span::current();
+    span.record("subscriber_email", form.email.as_str());
+    span.record("subscriber_name", form.name.as_str());
+    let bot_check = bot_protection.check(
+        form.website.as_deref(),
+        form.challenge.as_deref(),
+        form.nonce.as_deref(),
+        &form.email,
+    );
+    span.record("bot_check", bot_check.as_str());
+    if bot_check != BotCheck::Passed {
+        // Look exactly like a successful sign-up: bots get nothing to learn from.
+        tracing::info!("Dropping a sign-up that failed the bot checks");
+        metrics.record_subscription(SubscriptionOutcome::Bot);
+        return Ok(HttpResponse::Ok().finish());
+    }
+    let locale = negotiate_locale(&request, form.locale.as_deref(), &localization);
+    let new_subscriber = NewSubscriber::parse(form, locale).map_err(|errors| {
+        metrics.record_subscription(SubscriptionOutcome::ValidationFailure);
+        let errors = errors
+            .into_iter()
+            .map(|e| FieldError::from_input(e, locale))
+            .collect();
+        SubscribeError::ValidationError { errors, json }
+    })?;
+    // Counted whether or not an email goes out, so that the limit does not
+    // tell who is already on our list.
+    enforce(
+        &metrics,
+        rate_limiter
+            .check_email(new_subscriber.email.as_ref())
+            .await
+            .context("Failed to check the rate limit of an email address.")?,
+    )?;
     let mut transaction = pool
         .begin()
         .await
         .context("Failed to acquire a Postgres connection from the pool")?;
-    let subscriber_id = insert_subscriber(&mut transaction, &new_subscriber)
+    let list_ids = get_list_ids(&mut transaction, &new_subscriber.lists)
         .await
-        .context("Failed to insert new subscriber in the database.")?;
-    let subscription_token = generate_subscription_token();
-    store_token(&mut transaction, subscriber_id, &subscription_token)
+        .map_err(|e| lookup_error(&metrics, e, new_subscriber.locale, json))?;
+    // Existing subscribers are greeted with the name they gave us, not
+    // whatever the new sign-up claims.
+    let (subscriber_id, subscriber_name, subscription_token, outcome) =
+        match insert_subscriber(&mut transaction, &new_subscriber)
+            .await
+            .context("Failed to insert new subscriber in the database.")?
+        {
+            Some(subscriber_id) => {
+                add_to_lists(&mut transaction, subscriber_id, &list_ids)
+                    .await
+                    .context("Failed to add a new subscriber to the requested lists.")?;
+                let subscription_token = generate_subscription_token();
+                store_token(
+                    &mut transaction,
+                    subscriber_id,
+                    &subscription_token,
+                    &list_ids,
+                )
+                .await
+                .context("Failed to store the confirmation token for a new subscriber.")?;
+                (
+                    subscriber_id,
+                    new_subscriber.name.as_ref().to_owned(),
+                    Some(subscription_token),
+                    SubscriptionOutcome::Created,
+                )
+            }
+            None => {
+                let (subscriber_id, subscriber_name, subscription_token) =
+                    token_for_existing_subscriber(
+                        &mut transaction,
+                        &new_subscriber,
+                        &list_ids,
+                        Utc::now() - token_settings.ttl(),
+                    )
+                    .await?;
+                (
+                    subscriber_id,
+                    subscriber_name,
+                    subscription_token,
+                    SubscriptionOutcome::Duplicate,
+                )
+            }
+        };
+    if subscription_token.is_some() {
+        record_consent(
+            &mut transaction,
+            subscriber_id,
+            ConsentEvent::SignUp,
+            &new_subscriber.source,
+            &ConsentContext {
+                ip_address: client_ip,
+                ..ConsentContext::from_request(&request, &trusted_proxies)
+            },
+            &consent,
+        )
         .await
-        .context("Failed to store the confirmation token for a new subscriber.")?;
+        .context("Failed to record the consent of a new subscriber.")?;
+    }
     transaction
         .commit()
         .await
         .context("Failed to commit SQL transaction to store a new subscriber.")?;
-    send_confirmation_email(
-        &email_client,
-        new_subscriber,
-        &base_url.0,
-        &subscription_token,
+    let preferences_link = links.preferences_link(subscriber_id);
+    match subscription_token {
+        Some(subscription_token) => send_confirmation_email(
+            email_client.as_ref(),
+            &templates,
+            new_subscriber,
+            &subscriber_name,
+            &base_url.0,
+            &subscription_token,
+            &preferences_link,
+        )
+        .await
+        .context("Failed to send a confirmation email.")?,
+        // An email goes out either way, so that how long we take to answer
+        // does not reveal who is on our list.
+        None => {
+            tracing::info!("The subscriber has already confirmed all the requested lists");
+            send_already_subscribed_email(
+                email_client.as_ref(),
+                &templates,
+                new_subscriber,
+                &subscriber_name,
+                &preferences_link,
+            )
+            .await
+            .context("Failed to send an already subscribed notice.")?
+        }
+    }
+    metrics.record_subscription(outcome);
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Handle a sign-up for an email address that is already in `subscriptions`.
+///
+/// Returns the subscriber id, their stored name and the token to (re-)send
+/// in a confirmation email, or `None` if the subscriber has already
+/// confirmed every requested list and there is nothing to confirm.
+/// Pending subscribers get their latest token back if it was issued after
+/// `issued_after` for (at least) the same lists. Subscribers who had
+/// unsubscribed go back to `pending_confirmation`; both switch to the locale
+/// of their new sign-up.
+#[tracing::instrument(
+    name = "Handle a repeated sign-up",
+    skip(transaction, new_subscriber, list_ids)
+)]
+async fn token_for_existing_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    new_subscriber: &NewSubscriber,
+    list_ids: &[Uuid],
+    issued_after: DateTime<Utc>,
+) -> Result<(Uuid, String, Option<String>), SubscribeError> {
+    let existing = sqlx::query!(
+        r#"
+        SELECT id, name, status
+        FROM subscriptions
+        WHERE email = $1
+        FOR UPDATE
+        "#,
+        new_subscriber.email.as_ref(),
     )
+    .fetch_one(&mut *transaction)
     .await
-    .context("Failed to send a confirmation email.")?;
-    Ok(HttpResponse::Ok().finish())
+    .context("Failed to fetch the existing subscriber.")?;
+    add_to_lists(transaction, existing.id, list_ids)
+        .await
+        .context("Failed to add an existing subscriber to the requested lists.")?;
+    if !has_pending_memberships(transaction, existing.id, list_ids)
+        .await
+        .context("Failed to check the list memberships of an existing subscriber.")?
+    {
+        return Ok((existing.id, existing.name, None));
+    }
+    if existing.status == "unsubscribed" || existing.status == "pending_confirmation" {
+        sqlx::query!(
+            r#"
+            UPDATE subscriptions
+            SET status = 'pending_confirmation', locale = $2
+            WHERE id = $1
+            "#,
+            existing.id,
+            new_subscriber.locale.as_str(),
+        )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to reset the status of a returning subscriber.")?;
+    }
+    if let Some(subscription_token) =
+        get_token_for_subscriber(transaction, existing.id, list_ids, issued_after)
+            .await
+            .context("Failed to fetch the confirmation token of a pending subscriber.")?
+    {
+        return Ok((existing.id, existing.name, Some(subscription_token)));
+    }
+    let subscription_token = generate_subscription_token();
+    store_token(transaction, existing.id, &subscription_token, list_ids)
+        .await
+        .context("Failed to store the confirmation token for a returning subscriber.")?;
+    Ok((existing.id, existing.name, Some(subscription_token)))
+}
+
+/// Request membership of `list_ids`, pending confirmation.
+///
+/// Memberships that are already pending or confirmed are left alone.
+#[tracing::instrument(name = "Add subscriber to lists", skip(transaction))]
+pub async fn add_to_lists(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_ids: &[Uuid],
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO list_memberships (subscriber_id, list_id, status)
+        SELECT $1, list_id, 'pending_confirmation'
+        FROM UNNEST($2::uuid[]) AS list_id
+        ON CONFLICT (subscriber_id, list_id) DO UPDATE
+        SET status = 'pending_confirmation'
+        WHERE list_memberships.status = 'unsubscribed'
+        "#,
+        subscriber_id,
+        list_ids,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Check for pending list memberships", skip(transaction))]
+async fn has_pending_memberships(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_ids: &[Uuid],
+) -> Result<bool, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT EXISTS(
+            SELECT 1
+            FROM list_memberships
+            WHERE
+                subscriber_id = $1 AND
+                list_id = ANY($2) AND
+                status = 'pending_confirmation'
+        ) AS "pending!"
+        "#,
+        subscriber_id,
+        list_ids,
+    )
+    .fetch_one(transaction)
+    .await?;
+    Ok(row.pending)
+}
+
+/// A token that confirms fewer lists than `list_ids` will not do: a new
+/// one has to be issued.
+#[tracing::instrument(name = "Get the token of a pending subscriber", skip(transaction))]
+async fn get_token_for_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_ids: &[Uuid],
+    issued_after: DateTime<Utc>,
+) -> Result<Option<String>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT subscription_token
+        FROM subscription_tokens
+        WHERE
+            subscriber_id = $1 AND
+            consumed_at IS NULL AND
+            created_at > $2 AND
+            list_ids @> $3
+        ORDER BY created_at DESC
+        LIMIT 1
+        "#,
+        subscriber_id,
+        issued_after,
+        list_ids,
+    )
+    .fetch_optional(transaction)
+    .await?;
+    Ok(row.map(|r| r.subscription_token))
 }
 
 fn generate_subscription_token() -> String {
@@ -101,31 +572,83 @@ fn generate_subscription_token() -> String {
 
 #[tracing::instrument(
     name = "Send a confirmation email to a new subscriber",
-    skip(email_client, new_subscriber, base_url, subscription_token)
+    skip(
+        email_client,
+        templates,
+        new_subscriber,
+        subscriber_name,
+        base_url,
+        subscription_token,
+        preferences_link
+    )
 )]
 pub async fn send_confirmation_email(
-    email_client: &EmailClient,
+    email_client: &dyn EmailClient,
+    templates: &EmailTemplates,
     new_subscriber: NewSubscriber,
+    subscriber_name: &str,
     base_url: &str,
     subscription_token: &str,
-) -> Result<(), reqwest::Error> {
+    preferences_link: &str,
+) -> Result<(), anyhow::Error> {
     let confirmation_link = format!(
         "{}/subscriptions/confirm?subscription_token={}",
         base_url, subscription_token
     );
-    let plain_body = format!(
-        "Welcome to our newsletter!\nVisit {} to confirm your subscription.",
-        confirmation_link
-    );
-    let html_body = format!(
-        "Welcome to our newsletter!<br />Click <a href=\"{}\">here</a> to confirm your subscription.",
-        confirmation_link
-    );
+    let email = templates.confirmation(
+        new_subscriber.locale,
+        &ConfirmationEmail {
+            subscriber_name,
+            confirmation_link: &confirmation_link,
+            preferences_link,
+        },
+    )?;
     email_client
-        .send_email(new_subscriber.email, "Welcome!", &html_body, &plain_body)
+        .send_email(
+            &new_subscriber.email,
+            &email.subject,
+            &email.html,
+            &email.text,
+        )
+        .await
+}
+
+#[tracing::instrument(
+    name = "Send an already subscribed notice",
+    skip(
+        email_client,
+        templates,
+        new_subscriber,
+        subscriber_name,
+        preferences_link
+    )
+)]
+async fn send_already_subscribed_email(
+    email_client: &dyn EmailClient,
+    templates: &EmailTemplates,
+    new_subscriber: NewSubscriber,
+    subscriber_name: &str,
+    preferences_link: &str,
+) -> Result<(), anyhow::Error> {
+    let email = templates.already_subscribed(
+        new_subscriber.locale,
+        &AlreadySubscribedEmail {
+            subscriber_name,
+            preferences_link,
+        },
+    )?;
+    email_client
+        .send_email(
+            &new_subscriber.email,
+            &email.subject,
+            &email.html,
+            &email.text,
+        )
         .await
 }
 
+/// Returns `None`, without touching the existing row, if the email
+/// address is already in use.
 #[tracing::instrument(
     name = "Saving new subscriber details in the database",
     skip(new_subscriber, transaction)
@@ -133,23 +656,27 @@ pub async fn send_confirmation_email(
 pub async fn insert_subscriber(
     transaction: &mut Transaction<'_, Postgres>,
     new_subscriber: &NewSubscriber,
-) -> Result<Uuid, sqlx::Error> {
+) -> Result<Option<Uuid>, sqlx::Error> {
     let subscriber_id = Uuid::new_v4();
-    sqlx::query!(
+    let n_inserted_rows = sqlx::query!(
         r#"
-    INSERT INTO subscriptions (id, email, name, subscribed_at, status)
-    VALUES ($1, $2, $3, $4, 'pending_confirmation')
+    INSERT INTO subscriptions (id, email, name, subscribed_at, status, locale)
+    VALUES ($1, $2, $3, $4, 'pending_confirmation', $5)
+    ON CONFLICT (email) DO NOTHING
             "#,
         subscriber_id,
         new_subscriber.email.as_ref(),
         new_subscriber.name.as_ref(),
-        Utc::now()
+        Utc::now(),
+        new_subscriber.locale.as_str()
     )
     .execute(transaction)
-    .await?;
-    Ok(subscriber_id)
+    .await?
+    .rows_affected();
+    Ok(Some(subscriber_id).filter(|_| n_inserted_rows > 0))
 }
 
+/// The token will confirm the memberships of `list_ids`, and those only.
 #[tracing::instrument(
     name = "Store subscription token in the database",
     skip(subscription_token, transaction)
@@ -158,14 +685,16 @@ pub async fn store_token(
     transaction: &mut Transaction<'_, Postgres>,
     subscriber_id: Uuid,
     subscription_token: &str,
+    list_ids: &[Uuid],
 ) -> Result<(), StoreTokenError> {
     sqlx::query!(
         r#"
-    INSERT INTO subscription_tokens (subscription_token, subscriber_id)
-    VALUES ($1, $2)
+    INSERT INTO subscription_tokens (subscription_token, subscriber_id, list_ids)
+    VALUES ($1, $2, $3)
         "#,
         subscription_token,
-        subscriber_id
+        subscriber_id,
+        list_ids
     )
     .execute(transaction)
     .await
@@ -0,0 +1,17 @@
+use crate::bot_protection::{BotProtection, Challenge};
+use actix_web::http::header::CacheControl;
+use actix_web::http::header::CacheDirective;
+use actix_web::{web, HttpResponse};
+
+/// Hand out a proof-of-work challenge for the sign-up form.
+#[utoipa::path(
+    get,
+    path = "/subscriptions/challenge",
+    tag = "subscriptions",
+    responses((status = 200, description = "A fresh proof-of-work challenge.", body = Challenge))
+)]
+pub async fn sign_up_challenge(bot_protection: web::Data<BotProtection>) -> HttpResponse {
+    HttpResponse::Ok()
+        .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+        .json(bot_protection.issue_challenge())
+}
@@ -1,61 +1,215 @@
-use actix_web::{web, HttpResponse};
-use sqlx::PgPool;
+use crate::client_ip::TrustedProxies;
+use crate::configuration::{ConsentSettings, SubscriptionTokenSettings};
+use crate::consent::{record_consent, ConsentContext, ConsentEvent};
+use crate::metrics::{ConfirmationOutcome, Metrics};
+use crate::routes::error_chain_fmt;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct Parameters {
+    /// Sent in the confirmation email.
     subscription_token: String,
 }
 
-#[allow(clippy::async_yields_async)]
-#[tracing::instrument(name = "Confirm a pending subscriber", skip(parameters, pool))]
-pub async fn confirm(parameters: web::Query<Parameters>, pool: web::Data<PgPool>) -> HttpResponse {
-    let id = match get_subscriber_id_from_token(&pool, &parameters.subscription_token).await {
-        Ok(id) => id,
-        Err(_) => return HttpResponse::InternalServerError().finish(),
-    };
-    match id {
-        // Non-existing token!
-        None => HttpResponse::Unauthorized().finish(),
-        Some(subscriber_id) => {
-            if confirm_subscriber(&pool, subscriber_id).await.is_err() {
-                return HttpResponse::InternalServerError().finish();
-            }
-            HttpResponse::Ok().finish()
+#[derive(thiserror::Error)]
+pub enum ConfirmError {
+    #[error("The confirmation link is not valid.")]
+    UnknownToken,
+    #[error("The confirmation link has expired. Please subscribe again to get a new one.")]
+    ExpiredToken,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ConfirmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for ConfirmError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ConfirmError::UnknownToken => StatusCode::UNAUTHORIZED,
+            ConfirmError::ExpiredToken => StatusCode::GONE,
+            ConfirmError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
 }
 
-#[allow(clippy::async_yields_async)]
-#[tracing::instrument(name = "Mark subscriber as confirmed", skip(subscriber_id, pool))]
-pub async fn confirm_subscriber(pool: &PgPool, subscriber_id: Uuid) -> Result<(), sqlx::Error> {
+#[utoipa::path(
+    get,
+    path = "/subscriptions/confirm",
+    tag = "subscriptions",
+    params(Parameters),
+    responses(
+        (status = 200, description = "The subscription is confirmed."),
+        (status = 401, description = "The token is unknown.", body = String, content_type = "text/plain"),
+        (status = 410, description = "The token has expired.", body = String, content_type = "text/plain"),
+    )
+)]
+#[tracing::instrument(
+    name = "Confirm a pending subscriber",
+    skip(
+        parameters,
+        pool,
+        token_settings,
+        request,
+        trusted_proxies,
+        consent,
+        metrics
+    )
+)]
+pub async fn confirm(
+    parameters: web::Query<Parameters>,
+    pool: web::Data<PgPool>,
+    token_settings: web::Data<SubscriptionTokenSettings>,
+    request: HttpRequest,
+    trusted_proxies: web::Data<TrustedProxies>,
+    consent: web::Data<ConsentSettings>,
+    metrics: web::Data<Metrics>,
+) -> Result<HttpResponse, ConfirmError> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let token = get_token(&mut transaction, &parameters.subscription_token)
+        .await
+        .context("Failed to retrieve the subscription token.")?
+        .ok_or_else(|| {
+            metrics.record_confirmation(ConfirmationOutcome::UnknownToken);
+            ConfirmError::UnknownToken
+        })?;
+    if token.consumed_at.is_some() {
+        // Clicking the link twice is harmless: there is nothing left to do.
+        // A consumed token must not bring back someone who has since
+        // unsubscribed, though.
+        if token.subscriber_status == "confirmed" {
+            tracing::info!("The subscriber has already confirmed their subscription");
+            metrics.record_confirmation(ConfirmationOutcome::AlreadyConfirmed);
+            return Ok(HttpResponse::Ok().finish());
+        }
+        metrics.record_confirmation(ConfirmationOutcome::UnknownToken);
+        return Err(ConfirmError::UnknownToken);
+    }
+    if token.created_at + token_settings.ttl() < Utc::now() {
+        metrics.record_confirmation(ConfirmationOutcome::ExpiredToken);
+        return Err(ConfirmError::ExpiredToken);
+    }
+    confirm_subscriber(&mut transaction, token.subscriber_id, &token.list_ids)
+        .await
+        .context("Failed to update the subscriber status to `confirmed`.")?;
+    consume_token(&mut transaction, &parameters.subscription_token)
+        .await
+        .context("Failed to mark the subscription token as consumed.")?;
+    record_consent(
+        &mut transaction,
+        token.subscriber_id,
+        ConsentEvent::Confirmation,
+        "confirmation_link",
+        &ConsentContext::from_request(&request, &trusted_proxies),
+        &consent,
+    )
+    .await
+    .context("Failed to record the consent of a confirmed subscriber.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to confirm a subscriber.")?;
+    metrics.record_confirmation(ConfirmationOutcome::Confirmed);
+    Ok(HttpResponse::Ok().finish())
+}
+
+struct StoredToken {
+    subscriber_id: Uuid,
+    subscriber_status: String,
+    /// The lists the token was sent for.
+    list_ids: Vec<Uuid>,
+    created_at: DateTime<Utc>,
+    consumed_at: Option<DateTime<Utc>>,
+}
+
+#[tracing::instrument(name = "Get subscription token", skip(subscription_token, transaction))]
+async fn get_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscription_token: &str,
+) -> Result<Option<StoredToken>, sqlx::Error> {
+    sqlx::query_as!(
+        StoredToken,
+        r#"
+        SELECT
+            t.subscriber_id,
+            s.status AS subscriber_status,
+            t.list_ids,
+            t.created_at,
+            t.consumed_at
+        FROM subscription_tokens t
+        JOIN subscriptions s ON s.id = t.subscriber_id
+        WHERE t.subscription_token = $1
+        FOR UPDATE
+        "#,
+        subscription_token,
+    )
+    .fetch_optional(transaction)
+    .await
+}
+
+/// Confirm the subscriber and their pending memberships of `list_ids`.
+///
+/// Lists they were signed up to since, possibly by someone else, stay
+/// pending until the link sent for them is clicked.
+#[tracing::instrument(
+    name = "Mark subscriber as confirmed",
+    skip(subscriber_id, transaction)
+)]
+pub async fn confirm_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_ids: &[Uuid],
+) -> Result<(), sqlx::Error> {
     sqlx::query!(
         r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1"#,
         subscriber_id,
     )
-    .execute(pool)
-    .await
-    .map_err(|e| {
-        tracing::error!("Failed to execute query: {:?}", e);
-        e
-    })?;
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+        UPDATE list_memberships
+        SET status = 'confirmed'
+        WHERE
+            subscriber_id = $1 AND
+            list_id = ANY($2) AND
+            status = 'pending_confirmation'
+        "#,
+        subscriber_id,
+        list_ids,
+    )
+    .execute(transaction)
+    .await?;
     Ok(())
 }
 
-#[tracing::instrument(name = "Get subscriber_id from token", skip(subscription_token, pool))]
-pub async fn get_subscriber_id_from_token(
-    pool: &PgPool,
+#[tracing::instrument(name = "Mark subscription token as consumed", skip_all)]
+async fn consume_token(
+    transaction: &mut Transaction<'_, Postgres>,
     subscription_token: &str,
-) -> Result<Option<Uuid>, sqlx::Error> {
-    let result = sqlx::query!(
-        r#"SELECT subscriber_id FROM subscription_tokens WHERE subscription_token = $1"#,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE subscription_tokens
+        SET consumed_at = now()
+        WHERE subscription_token = $1
+        "#,
         subscription_token,
     )
-    .fetch_optional(pool)
-    .await
-    .map_err(|e| {
-        tracing::error!("Failed to execute query: {:?}", e);
-        e
-    })?;
-    Ok(result.map(|r| r.subscriber_id))
+    .execute(transaction)
+    .await?;
+    Ok(())
 }
@@ -0,0 +1,122 @@
+use crate::routes::error_chain_fmt;
+use crate::subscriber_data::{erase_subscriber, export_subscriber_data, SubscriberDataExport};
+use crate::subscriber_links::{LinkPurpose, SubscriberLinks};
+use actix_web::http::header::{ContentDisposition, ContentType, DispositionParam, DispositionType};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, ResponseError};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize, utoipa::IntoParams, utoipa::ToSchema)]
+#[into_params(parameter_in = Query)]
+pub struct SubscriberDataParameters {
+    #[param(value_type = String, format = Uuid)]
+    #[schema(value_type = String, format = Uuid)]
+    subscriber_id: Uuid,
+    token: String,
+}
+
+#[derive(thiserror::Error)]
+pub enum SubscriberDataError {
+    #[error("The link is invalid.")]
+    InvalidToken,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for SubscriberDataError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for SubscriberDataError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            SubscriberDataError::InvalidToken => StatusCode::UNAUTHORIZED,
+            SubscriberDataError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// Let subscribers download everything we store about them.
+#[utoipa::path(
+    get,
+    path = "/subscriptions/export",
+    tag = "subscriptions",
+    params(SubscriberDataParameters),
+    responses(
+        (status = 200, description = "Everything we store about the subscriber.", body = SubscriberDataExport),
+        (status = 401, description = "The link is invalid.", body = String, content_type = "text/plain"),
+    )
+)]
+#[tracing::instrument(
+    name = "Export own subscriber data",
+    skip(parameters, pool, links),
+    fields(subscriber_id = %parameters.subscriber_id)
+)]
+pub async fn export_own_data(
+    parameters: web::Query<SubscriberDataParameters>,
+    pool: web::Data<PgPool>,
+    links: web::Data<SubscriberLinks>,
+) -> Result<HttpResponse, SubscriberDataError> {
+    if !links.verify(
+        LinkPurpose::Export,
+        parameters.subscriber_id,
+        &parameters.token,
+    ) {
+        return Err(SubscriberDataError::InvalidToken);
+    }
+    let export = export_subscriber_data(&pool, parameters.subscriber_id)
+        .await
+        .context("Failed to export the subscriber data.")?
+        .ok_or(SubscriberDataError::InvalidToken)?;
+    Ok(HttpResponse::Ok()
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename("subscriber-data.json".into())],
+        })
+        .json(export))
+}
+
+/// Let subscribers erase their data, from the form in the preference center.
+#[utoipa::path(
+    post,
+    path = "/subscriptions/erase",
+    tag = "subscriptions",
+    request_body(content = SubscriberDataParameters, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 200, description = "The subscriber data is erased.", body = String, content_type = "text/html"),
+        (status = 401, description = "The link is invalid.", body = String, content_type = "text/plain"),
+    )
+)]
+#[tracing::instrument(
+    name = "Erase own subscriber data",
+    skip(form, pool, links),
+    fields(subscriber_id = %form.subscriber_id)
+)]
+pub async fn erase_own_data(
+    form: web::Form<SubscriberDataParameters>,
+    pool: web::Data<PgPool>,
+    links: web::Data<SubscriberLinks>,
+) -> Result<HttpResponse, SubscriberDataError> {
+    if !links.verify(LinkPurpose::Erase, form.subscriber_id, &form.token) {
+        return Err(SubscriberDataError::InvalidToken);
+    }
+    erase_subscriber(&pool, form.subscriber_id)
+        .await
+        .context("Failed to erase the subscriber data.")?;
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Data deleted</title>
+</head>
+<body>
+    <p>Your data has been deleted. You will not receive any further issues.</p>
+</body>
+</html>"#,
+    ))
+}
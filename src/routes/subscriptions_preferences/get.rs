@@ -0,0 +1,150 @@
+use super::{get_preferences, PreferencesError, SubscriberPreferences};
+use crate::domain::DigestFrequency;
+use crate::subscriber_links::{LinkPurpose, SubscriberLinks};
+use actix_web::http::header::{ContentType, ACCEPT};
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PreferencesParameters {
+    #[param(value_type = String, format = Uuid)]
+    subscriber_id: Uuid,
+    token: String,
+}
+
+/// Show the preference center, as HTML or, if asked for, as JSON.
+///
+/// Like the unsubscribe form, this page must not change any state.
+#[utoipa::path(
+    get,
+    path = "/subscriptions/preferences",
+    tag = "subscriptions",
+    params(PreferencesParameters),
+    responses(
+        (status = 200, description = "The preference center, as JSON if asked for in `Accept`.", content(
+            (SubscriberPreferences = "application/json"),
+            (String = "text/html"),
+        )),
+        (status = 401, description = "The link is invalid.", body = String, content_type = "text/plain"),
+    )
+)]
+#[tracing::instrument(
+    name = "Show the preference center",
+    skip(parameters, pool, links, request, flash_messages),
+    fields(subscriber_id = %parameters.subscriber_id)
+)]
+pub async fn preferences_form(
+    parameters: web::Query<PreferencesParameters>,
+    pool: web::Data<PgPool>,
+    links: web::Data<SubscriberLinks>,
+    request: HttpRequest,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, PreferencesError> {
+    let subscriber_id = parameters.subscriber_id;
+    if !links.verify(LinkPurpose::Preferences, subscriber_id, &parameters.token) {
+        return Err(PreferencesError::InvalidToken);
+    }
+    let preferences = get_preferences(&pool, &links, subscriber_id)
+        .await
+        .context("Failed to retrieve the subscriber preferences.")?
+        .ok_or(PreferencesError::InvalidToken)?;
+    if wants_json(&request) {
+        return Ok(HttpResponse::Ok().json(preferences));
+    }
+
+    let mut messages_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(
+            messages_html,
+            "<p><i>{}</i></p>",
+            htmlescape::encode_minimal(m.content())
+        )
+        .unwrap();
+    }
+    let mut lists_html = String::new();
+    for list in &preferences.lists {
+        writeln!(
+            lists_html,
+            r#"<label><input type="checkbox" name="lists" value="{}"{}> {}</label><br>"#,
+            htmlescape::encode_attribute(&list.slug),
+            if list.subscribed { " checked" } else { "" },
+            htmlescape::encode_minimal(&list.name)
+        )
+        .unwrap();
+    }
+    let mut frequencies_html = String::new();
+    for frequency in DigestFrequency::ALL {
+        writeln!(
+            frequencies_html,
+            r#"<option value="{0}"{1}>{0}</option>"#,
+            frequency.as_str(),
+            if frequency.as_str() == preferences.digest_frequency {
+                " selected"
+            } else {
+                ""
+            }
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Your preferences</title>
+</head>
+<body>
+    {messages_html}
+    <form action="/subscriptions/preferences" method="post">
+        <input hidden type="text" name="subscriber_id" value="{subscriber_id}">
+        <input hidden type="text" name="token" value="{token}">
+        <label>Name
+            <input type="text" name="name" value="{name}">
+        </label>
+        <fieldset>
+            <legend>Lists</legend>
+            {lists_html}
+        </fieldset>
+        <label>Digest frequency
+            <select name="digest_frequency">
+                {frequencies_html}
+            </select>
+        </label>
+        <button type="submit">Save preferences</button>
+    </form>
+    <form action="/subscriptions/unsubscribe" method="post">
+        <input hidden type="text" name="subscriber_id" value="{subscriber_id}">
+        <input hidden type="text" name="token" value="{unsubscribe_token}">
+        <button type="submit">Unsubscribe from everything</button>
+    </form>
+    <p><a href="{export_link}">Download your data</a></p>
+    <form action="/subscriptions/erase" method="post">
+        <input hidden type="text" name="subscriber_id" value="{subscriber_id}">
+        <input hidden type="text" name="token" value="{erase_token}">
+        <button type="submit">Delete all your data</button>
+    </form>
+</body>
+</html>"#,
+            token = htmlescape::encode_attribute(&parameters.token),
+            name = htmlescape::encode_attribute(&preferences.name),
+            unsubscribe_token = links.token(LinkPurpose::Unsubscribe, subscriber_id),
+            export_link = htmlescape::encode_attribute(&preferences.export_link),
+            erase_token = links.token(LinkPurpose::Erase, subscriber_id),
+        )))
+}
+
+fn wants_json(request: &HttpRequest) -> bool {
+    request
+        .headers()
+        .get(ACCEPT)
+        .and_then(|h| h.to_str().ok())
+        .map(|h| h.contains("application/json"))
+        .unwrap_or(false)
+}
@@ -0,0 +1,100 @@
+mod get;
+mod post;
+
+pub use get::{__path_preferences_form, preferences_form};
+pub use post::{__path_update_preferences, update_preferences};
+
+use crate::routes::error_chain_fmt;
+use crate::subscriber_links::SubscriberLinks;
+use actix_web::http::StatusCode;
+use actix_web::ResponseError;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(thiserror::Error)]
+pub enum PreferencesError {
+    #[error("The preferences link is invalid.")]
+    InvalidToken,
+    #[error("{0}")]
+    ValidationError(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for PreferencesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for PreferencesError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            PreferencesError::InvalidToken => StatusCode::UNAUTHORIZED,
+            PreferencesError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            PreferencesError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// What a subscriber can see and change in the preference center.
+#[derive(serde::Serialize, utoipa::ToSchema)]
+pub struct SubscriberPreferences {
+    name: String,
+    digest_frequency: String,
+    lists: Vec<ListPreference>,
+    unsubscribe_link: String,
+    export_link: String,
+}
+
+#[derive(serde::Serialize, utoipa::ToSchema)]
+pub struct ListPreference {
+    slug: String,
+    name: String,
+    subscribed: bool,
+}
+
+#[tracing::instrument(name = "Get subscriber preferences", skip(pool, links))]
+async fn get_preferences(
+    pool: &PgPool,
+    links: &SubscriberLinks,
+    subscriber_id: Uuid,
+) -> Result<Option<SubscriberPreferences>, sqlx::Error> {
+    let subscriber = match sqlx::query!(
+        r#"
+        SELECT name, digest_frequency
+        FROM subscriptions
+        WHERE id = $1 AND status <> 'erased'
+        "#,
+        subscriber_id,
+    )
+    .fetch_optional(pool)
+    .await?
+    {
+        Some(subscriber) => subscriber,
+        None => return Ok(None),
+    };
+    let lists = sqlx::query_as!(
+        ListPreference,
+        r#"
+        SELECT
+            l.slug,
+            l.name,
+            COALESCE(m.status <> 'unsubscribed', false) AS "subscribed!"
+        FROM lists l
+        LEFT JOIN list_memberships m
+            ON m.list_id = l.list_id AND m.subscriber_id = $1
+        ORDER BY l.slug
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(Some(SubscriberPreferences {
+        name: subscriber.name,
+        digest_frequency: subscriber.digest_frequency,
+        lists,
+        unsubscribe_link: links.unsubscribe_link(subscriber_id),
+        export_link: links.export_link(subscriber_id),
+    }))
+}
@@ -0,0 +1,305 @@
+use super::{get_preferences, PreferencesError, SubscriberPreferences};
+use crate::configuration::LocalizationSettings;
+use crate::domain::{DigestFrequency, InvalidInput, ListSlug, Locale, SubscriberName};
+use crate::lists::{get_list_ids, ListLookupError};
+use crate::localization::negotiate_locale;
+use crate::subscriber_links::{LinkPurpose, SubscriberLinks};
+use crate::utils::see_other;
+use actix_web::{web, Either, HttpRequest, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::{PgPool, Postgres, Transaction};
+use std::convert::{TryFrom, TryInto};
+use uuid::Uuid;
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub struct PreferencesUpdate {
+    #[schema(value_type = String, format = Uuid)]
+    subscriber_id: Uuid,
+    token: String,
+    name: String,
+    digest_frequency: String,
+    /// Slugs of the lists to stay on; every other list is left. Leaving
+    /// them all unsubscribes.
+    #[serde(default)]
+    lists: Vec<String>,
+}
+
+/// HTML forms send one `lists` pair per ticked checkbox, which
+/// `web::Form` cannot collect into a `Vec` on its own.
+impl TryFrom<Vec<(String, String)>> for PreferencesUpdate {
+    type Error = String;
+
+    fn try_from(pairs: Vec<(String, String)>) -> Result<Self, Self::Error> {
+        let field = |name: &str| {
+            pairs
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.clone())
+                .ok_or_else(|| format!("The `{}` field is missing.", name))
+        };
+        Ok(Self {
+            subscriber_id: field("subscriber_id")?
+                .parse()
+                .map_err(|_| "The subscriber id is not valid.".to_string())?,
+            token: field("token")?,
+            name: field("name")?,
+            digest_frequency: field("digest_frequency")?,
+            lists: pairs
+                .iter()
+                .filter(|(key, _)| key == "lists")
+                .map(|(_, value)| value.clone())
+                .collect(),
+        })
+    }
+}
+
+/// Save the preference center.
+///
+/// JSON requests get the updated preferences back; form submissions are
+/// redirected to the page with a flash message.
+#[utoipa::path(
+    post,
+    path = "/subscriptions/preferences",
+    tag = "subscriptions",
+    request_body(content(
+        (PreferencesUpdate = "application/json"),
+        (PreferencesUpdate = "application/x-www-form-urlencoded"),
+    )),
+    responses(
+        (status = 200, description = "The updated preferences, for JSON requests.", body = SubscriberPreferences),
+        (status = 303, description = "Back to the preference center, for form submissions."),
+        (status = 400, description = "The update is invalid.", body = String, content_type = "text/plain"),
+        (status = 401, description = "The link is invalid.", body = String, content_type = "text/plain"),
+    )
+)]
+#[tracing::instrument(name = "Update subscriber preferences", skip_all)]
+pub async fn update_preferences(
+    body: Either<web::Json<PreferencesUpdate>, web::Form<Vec<(String, String)>>>,
+    pool: web::Data<PgPool>,
+    links: web::Data<SubscriberLinks>,
+    request: HttpRequest,
+    localization: web::Data<LocalizationSettings>,
+) -> Result<HttpResponse, PreferencesError> {
+    let locale = negotiate_locale(&request, None, &localization);
+    match body {
+        Either::Left(update) => {
+            let subscriber_id = update.subscriber_id;
+            if !links.verify(LinkPurpose::Preferences, subscriber_id, &update.token) {
+                return Err(PreferencesError::InvalidToken);
+            }
+            save_preferences(&pool, update.into_inner(), locale).await?;
+            let preferences = get_preferences(&pool, &links, subscriber_id)
+                .await
+                .context("Failed to retrieve the subscriber preferences.")?
+                .ok_or(PreferencesError::InvalidToken)?;
+            Ok(HttpResponse::Ok().json(preferences))
+        }
+        Either::Right(form) => {
+            let update: PreferencesUpdate = form
+                .into_inner()
+                .try_into()
+                .map_err(PreferencesError::ValidationError)?;
+            if !links.verify(
+                LinkPurpose::Preferences,
+                update.subscriber_id,
+                &update.token,
+            ) {
+                return Err(PreferencesError::InvalidToken);
+            }
+            // A valid token is plain hex: it can go in the URL as is.
+            let page = format!(
+                "/subscriptions/preferences?subscriber_id={}&token={}",
+                update.subscriber_id, update.token
+            );
+            match save_preferences(&pool, update, locale).await {
+                Ok(()) => FlashMessage::info("Your preferences have been updated.").send(),
+                Err(PreferencesError::ValidationError(e)) => FlashMessage::error(e).send(),
+                Err(e) => return Err(e),
+            }
+            Ok(see_other(&page))
+        }
+    }
+}
+
+/// The caller must have verified `update.token`. Validation errors are
+/// worded in `locale`.
+#[tracing::instrument(
+    name = "Save subscriber preferences",
+    skip(pool, update),
+    fields(subscriber_id = %update.subscriber_id)
+)]
+async fn save_preferences(
+    pool: &PgPool,
+    update: PreferencesUpdate,
+    locale: Locale,
+) -> Result<(), PreferencesError> {
+    let subscriber_id = update.subscriber_id;
+    let name = SubscriberName::parse(update.name).map_err(|errors| {
+        let messages: Vec<_> = errors.iter().map(|e| e.localize(locale)).collect();
+        PreferencesError::ValidationError(messages.join(" "))
+    })?;
+    let digest_frequency = DigestFrequency::parse(update.digest_frequency)
+        .map_err(|e| PreferencesError::ValidationError(e.localize(locale)))?;
+    let lists = update
+        .lists
+        .into_iter()
+        .map(ListSlug::parse)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| PreferencesError::ValidationError(e.localize(locale)))?;
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let list_ids = get_list_ids(&mut transaction, &lists)
+        .await
+        .map_err(|e| match e {
+            ListLookupError::UnknownLists(lists) => PreferencesError::ValidationError(
+                InvalidInput::UnknownLists(lists).localize(locale),
+            ),
+            ListLookupError::DatabaseError(_) => PreferencesError::UnexpectedError(e.into()),
+        })?;
+    let status = sqlx::query!(
+        r#"
+        SELECT status
+        FROM subscriptions
+        WHERE id = $1 AND status <> 'erased'
+        FOR UPDATE
+        "#,
+        subscriber_id,
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to fetch the subscriber.")?
+    .ok_or(PreferencesError::InvalidToken)?
+    .status;
+    // The signed link proves that the subscriber owns the address, unless
+    // they still have to confirm it in the first place.
+    let pending = status == "pending_confirmation";
+    let membership_status = if pending {
+        "pending_confirmation"
+    } else {
+        "confirmed"
+    };
+    // Leaving every list is unsubscribing. A subscriber who has yet to
+    // confirm stays pending: they are sent nothing either way.
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET
+            name = $2,
+            digest_frequency = $3,
+            status = CASE
+                WHEN status = 'unsubscribed' AND $4 THEN 'confirmed'
+                WHEN status = 'confirmed' AND NOT $4 THEN 'unsubscribed'
+                ELSE status
+            END
+        WHERE id = $1
+        "#,
+        subscriber_id,
+        name.as_ref(),
+        digest_frequency.as_str(),
+        !list_ids.is_empty(),
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to update the subscriber details.")?;
+    // Issues held back for a digest go out no later than the new schedule.
+    sqlx::query!(
+        r#"
+        UPDATE issue_delivery_queue
+        SET execute_after = LEAST(execute_after, $2)
+        WHERE
+            n_retries = 0 AND
+            subscriber_email = (SELECT email FROM subscriptions WHERE id = $1)
+        "#,
+        subscriber_id,
+        digest_frequency.next_delivery(Utc::now()),
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to reschedule the pending deliveries.")?;
+    set_list_memberships(
+        &mut transaction,
+        subscriber_id,
+        &list_ids,
+        membership_status,
+    )
+    .await
+    .context("Failed to update the list memberships.")?;
+    if pending {
+        extend_pending_tokens(&mut transaction, subscriber_id, &list_ids)
+            .await
+            .context("Failed to add the new lists to the pending confirmation links.")?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to save subscriber preferences.")?;
+    Ok(())
+}
+
+/// Join `list_ids` with `status` and leave every other list. A confirmed
+/// membership is never demoted.
+#[tracing::instrument(name = "Set list memberships", skip(transaction))]
+async fn set_list_memberships(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_ids: &[Uuid],
+    status: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO list_memberships (subscriber_id, list_id, status)
+        SELECT $1, list_id, $3
+        FROM UNNEST($2::uuid[]) AS list_id
+        ON CONFLICT (subscriber_id, list_id) DO UPDATE
+        SET status = $3
+        WHERE list_memberships.status <> 'confirmed'
+        "#,
+        subscriber_id,
+        list_ids,
+        status,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+        UPDATE list_memberships
+        SET status = 'unsubscribed'
+        WHERE subscriber_id = $1 AND list_id <> ALL($2)
+        "#,
+        subscriber_id,
+        list_ids,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+/// Have the confirmation links already sent to a pending subscriber cover
+/// `list_ids` too, so that the lists they join before confirming are
+/// confirmed along with the others.
+#[tracing::instrument(name = "Extend pending subscription tokens", skip(transaction))]
+async fn extend_pending_tokens(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_ids: &[Uuid],
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE subscription_tokens
+        SET list_ids = ARRAY(
+            SELECT DISTINCT list_id FROM UNNEST(list_ids || $2::uuid[]) AS list_id
+        )
+        WHERE subscriber_id = $1 AND consumed_at IS NULL
+        "#,
+        subscriber_id,
+        list_ids,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,158 @@
+use crate::routes::error_chain_fmt;
+use crate::subscriber_links::{LinkPurpose, SubscriberLinks};
+use actix_web::http::header::ContentType;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, ResponseError};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize, utoipa::IntoParams, utoipa::ToSchema)]
+#[into_params(parameter_in = Query)]
+pub struct UnsubscribeParameters {
+    #[param(value_type = String, format = Uuid)]
+    #[schema(value_type = String, format = Uuid)]
+    subscriber_id: Uuid,
+    token: String,
+}
+
+#[derive(thiserror::Error)]
+pub enum UnsubscribeError {
+    #[error("The unsubscribe link is invalid.")]
+    InvalidToken,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for UnsubscribeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for UnsubscribeError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            UnsubscribeError::InvalidToken => StatusCode::UNAUTHORIZED,
+            UnsubscribeError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// Ask for confirmation before unsubscribing.
+///
+/// Mail scanners pre-fetch links: following one must not change any state.
+#[utoipa::path(
+    get,
+    path = "/subscriptions/unsubscribe",
+    tag = "subscriptions",
+    params(UnsubscribeParameters),
+    responses(
+        (status = 200, description = "A page asking to confirm.", body = String, content_type = "text/html"),
+        (status = 401, description = "The link is invalid.", body = String, content_type = "text/plain"),
+    )
+)]
+#[tracing::instrument(
+    name = "Show the unsubscribe form",
+    skip(parameters, links),
+    fields(subscriber_id = %parameters.subscriber_id)
+)]
+pub async fn unsubscribe_form(
+    parameters: web::Query<UnsubscribeParameters>,
+    links: web::Data<SubscriberLinks>,
+) -> Result<HttpResponse, UnsubscribeError> {
+    if !links.verify(
+        LinkPurpose::Unsubscribe,
+        parameters.subscriber_id,
+        &parameters.token,
+    ) {
+        return Err(UnsubscribeError::InvalidToken);
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Unsubscribe</title>
+</head>
+<body>
+    <p>Do you want to stop receiving our newsletter?</p>
+    <form action="/subscriptions/unsubscribe" method="post">
+        <input hidden type="text" name="subscriber_id" value="{}">
+        <input hidden type="text" name="token" value="{}">
+        <button type="submit">Unsubscribe</button>
+    </form>
+</body>
+</html>"#,
+            parameters.subscriber_id,
+            htmlescape::encode_attribute(&parameters.token)
+        )))
+}
+
+#[utoipa::path(
+    post,
+    path = "/subscriptions/unsubscribe",
+    tag = "subscriptions",
+    request_body(content = UnsubscribeParameters, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 200, description = "The subscriber is unsubscribed from every list.", body = String, content_type = "text/html"),
+        (status = 401, description = "The link is invalid.", body = String, content_type = "text/plain"),
+    )
+)]
+#[tracing::instrument(
+    name = "Unsubscribe a subscriber",
+    skip(form, pool, links),
+    fields(subscriber_id = %form.subscriber_id)
+)]
+pub async fn unsubscribe(
+    form: web::Form<UnsubscribeParameters>,
+    pool: web::Data<PgPool>,
+    links: web::Data<SubscriberLinks>,
+) -> Result<HttpResponse, UnsubscribeError> {
+    if !links.verify(LinkPurpose::Unsubscribe, form.subscriber_id, &form.token) {
+        return Err(UnsubscribeError::InvalidToken);
+    }
+    mark_subscriber_as_unsubscribed(&pool, form.subscriber_id)
+        .await
+        .context("Failed to unsubscribe the subscriber.")?;
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Unsubscribed</title>
+</head>
+<body>
+    <p>You have been unsubscribed. You will not receive any further issues.</p>
+</body>
+</html>"#,
+    ))
+}
+
+/// Unsubscribe from every list at once.
+#[tracing::instrument(name = "Mark subscriber as unsubscribed", skip(pool))]
+pub async fn mark_subscriber_as_unsubscribed(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET status = 'unsubscribed'
+        WHERE id = $1 AND status <> 'erased'
+        "#,
+        subscriber_id,
+    )
+    .execute(&mut transaction)
+    .await?;
+    sqlx::query!(
+        r#"UPDATE list_memberships SET status = 'unsubscribed' WHERE subscriber_id = $1"#,
+        subscriber_id,
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await
+}
@@ -0,0 +1,93 @@
+//! Coordinated, graceful shutdown of the API and the background workers.
+use std::sync::Arc;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::watch;
+
+/// Tells everything running in the process that it is time to wind down.
+///
+/// Clones share their state: triggering one triggers them all.
+#[derive(Clone)]
+pub struct Shutdown(Arc<watch::Sender<bool>>);
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self(Arc::new(watch::channel(false).0))
+    }
+
+    /// Idempotent.
+    pub fn trigger(&self) {
+        self.0.send_replace(true);
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    /// Resolves once the shutdown has been triggered, straight away if it
+    /// already has been.
+    pub async fn triggered(&self) {
+        let mut receiver = self.0.subscribe();
+        // The sender lives as long as `self`: this cannot fail.
+        let _ = receiver.wait_for(|triggered| *triggered).await;
+    }
+
+    /// Sleep for `duration`, waking up early if the shutdown is triggered.
+    pub async fn sleep(&self, duration: std::time::Duration) {
+        tokio::select! {
+            _ = tokio::time::sleep(duration) => {}
+            _ = self.triggered() => {}
+        }
+    }
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Wait for SIGTERM, as sent by orchestrators, or SIGINT, as sent by Ctrl+C.
+/// Returns the name of the signal received.
+pub async fn termination_signal() -> Result<&'static str, std::io::Error> {
+    let mut sigterm = signal(SignalKind::terminate())?;
+    let mut sigint = signal(SignalKind::interrupt())?;
+    let name = tokio::select! {
+        _ = sigterm.recv() => "SIGTERM",
+        _ = sigint.recv() => "SIGINT",
+    };
+    Ok(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Shutdown;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn every_clone_sees_the_trigger() {
+        let shutdown = Shutdown::new();
+        let clone = shutdown.clone();
+        assert!(!clone.is_triggered());
+
+        shutdown.trigger();
+
+        assert!(clone.is_triggered());
+        tokio::time::timeout(Duration::from_secs(1), clone.triggered())
+            .await
+            .expect("`triggered` should resolve once triggered.");
+    }
+
+    #[tokio::test]
+    async fn sleeping_is_cut_short_by_the_trigger() {
+        let shutdown = Shutdown::new();
+        let sleeper = shutdown.clone();
+        let sleeping = tokio::spawn(async move { sleeper.sleep(Duration::from_secs(60)).await });
+
+        shutdown.trigger();
+
+        tokio::time::timeout(Duration::from_secs(1), sleeping)
+            .await
+            .expect("The sleep should have been interrupted.")
+            .unwrap();
+    }
+}
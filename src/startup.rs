@@ -1,33 +1,81 @@
-use crate::configuration::{DatabaseSettings, Settings};
+use crate::authentication::{reject_anonymous_users, seed_admin};
+use crate::bot_protection::BotProtection;
+use crate::client_ip::TrustedProxies;
+use crate::configuration::{
+    ConsentSettings, DatabaseSettings, IdempotencySettings, LocalizationSettings, Settings,
+    SubscriptionTokenSettings,
+};
 use crate::email_client::EmailClient;
-use crate::routes::{confirm, health_check, subscribe};
+use crate::email_templates::EmailTemplates;
+use crate::health::ReadinessProbe;
+use crate::metrics::{track_requests, Metrics};
+use crate::rate_limit::RateLimiter;
+use crate::routes::{
+    admin_dashboard, api_docs, confirm, erase_own_data, erase_subscriber_data, export_own_data,
+    export_subscriber, health_check, liveness, log_out, login, login_form, openapi_json,
+    preferences_form, publish_newsletter, readiness, scrape_metrics, sign_up_challenge, subscribe,
+    subscriber_detail, unsubscribe, unsubscribe_form, update_preferences,
+};
+use crate::session_store::PostgresSessionStore;
+use crate::shutdown::Shutdown;
+use crate::subscriber_links::SubscriberLinks;
+use crate::telemetry::TraceContextRootSpan;
+use actix_session::SessionMiddleware;
+use actix_web::cookie::Key;
 use actix_web::dev::Server;
+use actix_web::middleware::from_fn;
 use actix_web::web::Data;
 use actix_web::{web, App, HttpServer};
+use actix_web_flash_messages::storage::CookieMessageStore;
+use actix_web_flash_messages::FlashMessagesFramework;
+use secrecy::{ExposeSecret, Secret};
 use sqlx::postgres::PgPoolOptions;
 use sqlx::PgPool;
 use std::net::TcpListener;
+use std::sync::Arc;
+use std::time::Duration;
 use tracing_actix_web::TracingLogger;
 
 pub struct Application {
     port: u16,
+    /// Set when `/metrics` has a listener of its own.
+    admin_port: Option<u16>,
     server: Server,
+    admin_server: Option<Server>,
+    metrics: Arc<Metrics>,
+    connection_pool: PgPool,
+    shutdown: Shutdown,
+    pre_stop_delay: Duration,
 }
 
 impl Application {
-    pub async fn build(configuration: Settings) -> Result<Self, std::io::Error> {
+    /// Fails, listing every problem, on an invalid configuration: nothing is
+    /// bound or connected to until it has been checked.
+    pub async fn build(configuration: Settings) -> Result<Self, anyhow::Error> {
+        configuration.validate()?;
         let connection_pool = get_connection_pool(&configuration.database);
-        let sender_email = configuration
-            .email_client
-            .sender()
-            .expect("Invalid sender email address.");
-        let timeout = configuration.email_client.timeout();
-        let email_client = EmailClient::new(
-            configuration.email_client.base_url,
-            sender_email,
-            configuration.email_client.authorization_token,
-            timeout,
+        if let Some(password_hash) = &configuration.admin.password_hash {
+            let username = &configuration.admin.username;
+            if seed_admin(username, password_hash, &connection_pool).await? {
+                tracing::info!("Created the `{}` account", username);
+            }
+        }
+        let metrics = Arc::new(Metrics::new()?);
+        metrics.register_pool("api", connection_pool.clone());
+        let email_client = configuration.email_client.metered_client(metrics.clone())?;
+        let email_templates = EmailTemplates::load(&configuration.email_templates)?;
+        let rate_limiter = configuration.rate_limits.limiter(connection_pool.clone());
+        let bot_protection = configuration
+            .bot_protection
+            .bot_protection(configuration.application.hmac_secret.clone());
+        let shutdown = Shutdown::new();
+        let readiness_probe = configuration.health.readiness_probe(
+            connection_pool.clone(),
+            email_client.clone(),
+            shutdown.clone(),
         );
+        let grace_period = configuration.application.shutdown_grace_period();
+        let pre_stop_delay = configuration.application.shutdown_pre_stop_delay();
 
         let address = format!(
             "{}:{}",
@@ -35,22 +83,100 @@ impl Application {
         );
         let listener = TcpListener::bind(&address)?;
         let port = listener.local_addr().unwrap().port();
+        let (admin_port, admin_server) = match configuration.application.admin_port {
+            Some(admin_port) => {
+                let listener = TcpListener::bind(format!(
+                    "{}:{}",
+                    configuration.application.host, admin_port
+                ))?;
+                let admin_port = listener.local_addr().unwrap().port();
+                let admin_server = run_admin(listener, metrics.clone(), grace_period)?;
+                (Some(admin_port), Some(admin_server))
+            }
+            None => (None, None),
+        };
         let server = run(
             listener,
-            connection_pool,
+            connection_pool.clone(),
             email_client,
+            email_templates,
             configuration.application.base_url,
+            configuration.application.hmac_secret,
+            configuration.application.api_docs_page,
+            TrustedProxies(configuration.application.trusted_proxies),
+            rate_limiter,
+            bot_protection,
+            readiness_probe,
+            metrics.clone(),
+            admin_server.is_none(),
+            grace_period,
+            configuration.subscription_tokens,
+            configuration.idempotency,
+            configuration.consent,
+            configuration.localization,
         )?;
 
-        Ok(Self { port, server })
+        Ok(Self {
+            port,
+            admin_port,
+            server,
+            admin_server,
+            metrics,
+            connection_pool,
+            shutdown,
+            pre_stop_delay,
+        })
     }
 
     pub fn port(&self) -> u16 {
         self.port
     }
 
+    pub fn admin_port(&self) -> Option<u16> {
+        self.admin_port
+    }
+
+    /// For the background workers to report to.
+    pub fn metrics(&self) -> Arc<Metrics> {
+        self.metrics.clone()
+    }
+
+    /// Triggering it stops the application, as does SIGTERM or SIGINT in
+    /// `main`. The background workers should be given the same handle.
+    pub fn shutdown(&self) -> Shutdown {
+        self.shutdown.clone()
+    }
+
+    /// Serve until shut down, then stop gracefully: readiness fails first,
+    /// for the pre-stop delay, then new connections are refused and
+    /// in-flight requests are given the grace period to complete before the
+    /// connection pool is closed.
     pub async fn run_until_stopped(self) -> Result<(), std::io::Error> {
-        self.server.await
+        let server_handle = self.server.handle();
+        let admin_server_handle = self.admin_server.as_ref().map(Server::handle);
+        let shutdown = self.shutdown.clone();
+        let pre_stop_delay = self.pre_stop_delay;
+        tokio::spawn(async move {
+            shutdown.triggered().await;
+            tracing::info!(
+                "Shutting down, failing readiness checks for {:?} before refusing connections",
+                pre_stop_delay
+            );
+            tokio::time::sleep(pre_stop_delay).await;
+            tracing::info!("Shutting down, no longer accepting connections");
+            let stop_admin_server = async {
+                if let Some(handle) = admin_server_handle {
+                    handle.stop(true).await;
+                }
+            };
+            tokio::join!(server_handle.stop(true), stop_admin_server);
+        });
+        let outcome = match self.admin_server {
+            Some(admin_server) => tokio::try_join!(self.server, admin_server).map(|_| ()),
+            None => self.server.await,
+        };
+        self.connection_pool.close().await;
+        outcome
     }
 }
 
@@ -62,25 +188,134 @@ pub fn get_connection_pool(configuration: &DatabaseSettings) -> PgPool {
 
 pub struct ApplicationBaseUrl(pub String);
 
+#[allow(clippy::too_many_arguments)]
 fn run(
     listener: TcpListener,
     db_pool: PgPool,
-    email_client: EmailClient,
+    email_client: Arc<dyn EmailClient>,
+    email_templates: EmailTemplates,
     base_url: String,
+    hmac_secret: Secret<String>,
+    api_docs_page: bool,
+    trusted_proxies: TrustedProxies,
+    rate_limiter: RateLimiter,
+    bot_protection: BotProtection,
+    readiness_probe: ReadinessProbe,
+    metrics: Arc<Metrics>,
+    serve_metrics: bool,
+    shutdown_grace_period: Duration,
+    subscription_tokens: SubscriptionTokenSettings,
+    idempotency: IdempotencySettings,
+    consent: ConsentSettings,
+    localization: LocalizationSettings,
 ) -> Result<Server, std::io::Error> {
+    let secret_key = Key::from(hmac_secret.expose_secret().as_bytes());
+    let message_store = CookieMessageStore::builder(secret_key.clone()).build();
+    let message_framework = FlashMessagesFramework::builder(message_store).build();
+    let session_store = PostgresSessionStore::new(db_pool.clone());
+    let subscriber_links = Data::new(SubscriberLinks::new(base_url.clone(), hmac_secret));
     let db_pool = Data::new(db_pool);
-    let email_client = Data::new(email_client);
+    let email_client: Data<dyn EmailClient> = Data::from(email_client);
+    let email_templates = Data::new(email_templates);
     let base_url = Data::new(ApplicationBaseUrl(base_url));
+    let subscription_tokens = Data::new(subscription_tokens);
+    let idempotency = Data::new(idempotency);
+    let consent = Data::new(consent);
+    let localization = Data::new(localization);
+    let trusted_proxies = Data::new(trusted_proxies);
+    let rate_limiter = Data::new(rate_limiter);
+    let bot_protection = Data::new(bot_protection);
+    let readiness_probe = Data::new(readiness_probe);
+    let metrics = Data::from(metrics);
     let server = HttpServer::new(move || {
         App::new()
-            .wrap(TracingLogger::default())
+            .wrap(message_framework.clone())
+            .wrap(SessionMiddleware::new(
+                session_store.clone(),
+                secret_key.clone(),
+            ))
+            .wrap(from_fn(track_requests))
+            .wrap(TracingLogger::<TraceContextRootSpan>::new())
             .route("/health_check", web::get().to(health_check))
+            .route("/health/live", web::get().to(liveness))
+            .route("/health/ready", web::get().to(readiness))
+            .route("/login", web::get().to(login_form))
+            .route("/login", web::post().to(login))
+            .service(
+                web::scope("/admin")
+                    .wrap(from_fn(reject_anonymous_users))
+                    .route("/dashboard", web::get().to(admin_dashboard))
+                    .route("/logout", web::post().to(log_out))
+                    .route("/subscribers", web::get().to(subscriber_detail))
+                    .route("/subscribers/export", web::get().to(export_subscriber))
+                    .route("/subscribers/erase", web::post().to(erase_subscriber_data)),
+            )
             .route("/subscriptions", web::post().to(subscribe))
+            .route("/subscriptions/challenge", web::get().to(sign_up_challenge))
             .route("/subscriptions/confirm", web::get().to(confirm))
+            .route(
+                "/subscriptions/unsubscribe",
+                web::get().to(unsubscribe_form),
+            )
+            .route("/subscriptions/unsubscribe", web::post().to(unsubscribe))
+            .route(
+                "/subscriptions/preferences",
+                web::get().to(preferences_form),
+            )
+            .route(
+                "/subscriptions/preferences",
+                web::post().to(update_preferences),
+            )
+            .route("/subscriptions/export", web::get().to(export_own_data))
+            .route("/subscriptions/erase", web::post().to(erase_own_data))
+            .route("/newsletters", web::post().to(publish_newsletter))
+            .route("/openapi.json", web::get().to(openapi_json))
+            .configure(|cfg| {
+                if api_docs_page {
+                    cfg.route("/api-docs", web::get().to(api_docs));
+                }
+                if serve_metrics {
+                    cfg.route("/metrics", web::get().to(scrape_metrics));
+                }
+            })
             .app_data(db_pool.clone())
             .app_data(email_client.clone())
+            .app_data(email_templates.clone())
             .app_data(base_url.clone())
+            .app_data(subscriber_links.clone())
+            .app_data(subscription_tokens.clone())
+            .app_data(idempotency.clone())
+            .app_data(consent.clone())
+            .app_data(localization.clone())
+            .app_data(trusted_proxies.clone())
+            .app_data(rate_limiter.clone())
+            .app_data(bot_protection.clone())
+            .app_data(readiness_probe.clone())
+            .app_data(metrics.clone())
+    })
+    // Signals are handled by `main`, readiness must fail before we stop.
+    .disable_signals()
+    .shutdown_timeout(shutdown_grace_period.as_secs())
+    .listen(listener)?
+    .run();
+    Ok(server)
+}
+
+/// The server behind `ApplicationSettings::admin_port`, for operators only.
+fn run_admin(
+    listener: TcpListener,
+    metrics: Arc<Metrics>,
+    shutdown_grace_period: Duration,
+) -> Result<Server, std::io::Error> {
+    let metrics = Data::from(metrics);
+    let server = HttpServer::new(move || {
+        App::new()
+            .wrap(TracingLogger::<TraceContextRootSpan>::new())
+            .route("/metrics", web::get().to(scrape_metrics))
+            .app_data(metrics.clone())
     })
+    .disable_signals()
+    .shutdown_timeout(shutdown_grace_period.as_secs())
     .listen(listener)?
     .run();
     Ok(server)
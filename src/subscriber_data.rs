@@ -0,0 +1,244 @@
+//! Everything we store about a subscriber, for subject-access and erasure
+//! requests.
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Serialize, utoipa::ToSchema)]
+pub struct SubscriberDataExport {
+    pub subscriber: SubscriberRecord,
+    pub list_memberships: Vec<ListMembershipRecord>,
+    pub subscription_tokens: Vec<SubscriptionTokenRecord>,
+    pub deliveries: Vec<DeliveryRecord>,
+    pub pending_deliveries: Vec<PendingDeliveryRecord>,
+    pub consent_records: Vec<ConsentRecord>,
+}
+
+#[derive(serde::Serialize, utoipa::ToSchema)]
+pub struct SubscriberRecord {
+    #[schema(value_type = String, format = Uuid)]
+    pub id: Uuid,
+    pub email: String,
+    pub name: String,
+    pub status: String,
+    pub digest_frequency: String,
+    pub locale: String,
+    pub subscribed_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize, utoipa::ToSchema)]
+pub struct ListMembershipRecord {
+    pub list: String,
+    pub status: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize, utoipa::ToSchema)]
+pub struct SubscriptionTokenRecord {
+    pub subscription_token: String,
+    pub created_at: DateTime<Utc>,
+    pub consumed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(serde::Serialize, utoipa::ToSchema)]
+pub struct DeliveryRecord {
+    #[schema(value_type = String, format = Uuid)]
+    pub newsletter_issue_id: Uuid,
+    pub title: String,
+    pub outcome: String,
+    pub recorded_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize, utoipa::ToSchema)]
+pub struct PendingDeliveryRecord {
+    #[schema(value_type = String, format = Uuid)]
+    pub newsletter_issue_id: Uuid,
+    pub title: String,
+    pub n_retries: i16,
+    pub execute_after: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize, utoipa::ToSchema)]
+pub struct ConsentRecord {
+    pub event: String,
+    pub recorded_at: DateTime<Utc>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub source: String,
+    pub privacy_policy_version: String,
+}
+
+#[tracing::instrument(name = "Find subscriber by email", skip(pool, email))]
+pub async fn find_subscriber_by_email(
+    pool: &PgPool,
+    email: &str,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let row = sqlx::query!(r#"SELECT id FROM subscriptions WHERE email = $1"#, email)
+        .fetch_optional(pool)
+        .await?;
+    Ok(row.map(|r| r.id))
+}
+
+/// Returns `None` if there is no such subscriber, or nothing left to export
+/// because they were erased.
+#[tracing::instrument(name = "Export subscriber data", skip(pool))]
+pub async fn export_subscriber_data(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Option<SubscriberDataExport>, sqlx::Error> {
+    let subscriber = match sqlx::query_as!(
+        SubscriberRecord,
+        r#"
+        SELECT id, email, name, status, digest_frequency, locale, subscribed_at
+        FROM subscriptions
+        WHERE id = $1 AND status <> 'erased'
+        "#,
+        subscriber_id,
+    )
+    .fetch_optional(pool)
+    .await?
+    {
+        Some(subscriber) => subscriber,
+        None => return Ok(None),
+    };
+    let list_memberships = sqlx::query_as!(
+        ListMembershipRecord,
+        r#"
+        SELECT l.slug AS list, m.status, m.created_at
+        FROM list_memberships m
+        JOIN lists l ON l.list_id = m.list_id
+        WHERE m.subscriber_id = $1
+        ORDER BY m.created_at
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(pool)
+    .await?;
+    let subscription_tokens = sqlx::query_as!(
+        SubscriptionTokenRecord,
+        r#"
+        SELECT subscription_token, created_at, consumed_at
+        FROM subscription_tokens
+        WHERE subscriber_id = $1
+        ORDER BY created_at
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(pool)
+    .await?;
+    let deliveries = sqlx::query_as!(
+        DeliveryRecord,
+        r#"
+        SELECT d.newsletter_issue_id, i.title, d.outcome, d.recorded_at
+        FROM issue_delivery_log d
+        JOIN newsletter_issues i ON i.newsletter_issue_id = d.newsletter_issue_id
+        WHERE d.subscriber_id = $1
+        ORDER BY d.recorded_at
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(pool)
+    .await?;
+    let pending_deliveries = sqlx::query_as!(
+        PendingDeliveryRecord,
+        r#"
+        SELECT q.newsletter_issue_id, i.title, q.n_retries, q.execute_after
+        FROM issue_delivery_queue q
+        JOIN newsletter_issues i ON i.newsletter_issue_id = q.newsletter_issue_id
+        WHERE q.subscriber_email = $1
+        ORDER BY q.execute_after
+        "#,
+        subscriber.email,
+    )
+    .fetch_all(pool)
+    .await?;
+    let consent_records = sqlx::query_as!(
+        ConsentRecord,
+        r#"
+        SELECT event, recorded_at, ip_address, user_agent, source, privacy_policy_version
+        FROM consent_records
+        WHERE subscriber_id = $1
+        ORDER BY recorded_at
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(Some(SubscriberDataExport {
+        subscriber,
+        list_memberships,
+        subscription_tokens,
+        deliveries,
+        pending_deliveries,
+        consent_records,
+    }))
+}
+
+/// Irreversibly remove a subscriber's personal data.
+///
+/// The `subscriptions` row, its list memberships, consent records and the
+/// delivery log are kept, stripped of anything identifying, so that
+/// per-list and per-issue counts do not change. Returns `false` if there was nobody to erase.
+#[tracing::instrument(name = "Erase subscriber data", skip(pool))]
+pub async fn erase_subscriber(pool: &PgPool, subscriber_id: Uuid) -> Result<bool, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    let email = match sqlx::query!(
+        r#"
+        SELECT email
+        FROM subscriptions
+        WHERE id = $1 AND status <> 'erased'
+        FOR UPDATE
+        "#,
+        subscriber_id,
+    )
+    .fetch_optional(&mut transaction)
+    .await?
+    {
+        Some(row) => row.email,
+        None => return Ok(false),
+    };
+    sqlx::query!(
+        r#"DELETE FROM issue_delivery_queue WHERE subscriber_email = $1"#,
+        email,
+    )
+    .execute(&mut transaction)
+    .await?;
+    sqlx::query!(
+        r#"DELETE FROM subscription_tokens WHERE subscriber_id = $1"#,
+        subscriber_id,
+    )
+    .execute(&mut transaction)
+    .await?;
+    sqlx::query!(
+        r#"UPDATE list_memberships SET status = 'unsubscribed' WHERE subscriber_id = $1"#,
+        subscriber_id,
+    )
+    .execute(&mut transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+        UPDATE consent_records
+        SET ip_address = NULL, user_agent = NULL
+        WHERE subscriber_id = $1
+        "#,
+        subscriber_id,
+    )
+    .execute(&mut transaction)
+    .await?;
+    // The email column is unique: derive the placeholder from the id.
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET
+            email = id::text || '@erased.invalid',
+            name = 'erased',
+            status = 'erased'
+        WHERE id = $1
+        "#,
+        subscriber_id,
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(true)
+}
@@ -0,0 +1,164 @@
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use uuid::Uuid;
+
+/// What a signed subscriber link lets its bearer do.
+///
+/// The purpose is part of the signed payload, so a token issued for one
+/// action cannot be replayed against another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkPurpose {
+    Unsubscribe,
+    Preferences,
+    Export,
+    Erase,
+}
+
+impl LinkPurpose {
+    fn as_str(&self) -> &'static str {
+        match self {
+            LinkPurpose::Unsubscribe => "unsubscribe",
+            LinkPurpose::Preferences => "preferences",
+            LinkPurpose::Export => "export",
+            LinkPurpose::Erase => "erase",
+        }
+    }
+
+    fn path(&self) -> &'static str {
+        match self {
+            LinkPurpose::Unsubscribe => "/subscriptions/unsubscribe",
+            LinkPurpose::Preferences => "/subscriptions/preferences",
+            LinkPurpose::Export => "/subscriptions/export",
+            LinkPurpose::Erase => "/subscriptions/erase",
+        }
+    }
+}
+
+/// Builds and verifies links that identify a subscriber without a login.
+///
+/// Each link carries the subscriber id and an HMAC-SHA256 tag over it:
+/// nobody without the application's `hmac_secret` can forge one.
+#[derive(Clone)]
+pub struct SubscriberLinks {
+    base_url: String,
+    hmac_secret: Secret<String>,
+}
+
+impl SubscriberLinks {
+    pub fn new(base_url: String, hmac_secret: Secret<String>) -> Self {
+        Self {
+            base_url,
+            hmac_secret,
+        }
+    }
+
+    pub fn unsubscribe_link(&self, subscriber_id: Uuid) -> String {
+        self.link(LinkPurpose::Unsubscribe, subscriber_id)
+    }
+
+    pub fn preferences_link(&self, subscriber_id: Uuid) -> String {
+        self.link(LinkPurpose::Preferences, subscriber_id)
+    }
+
+    pub fn export_link(&self, subscriber_id: Uuid) -> String {
+        self.link(LinkPurpose::Export, subscriber_id)
+    }
+
+    fn link(&self, purpose: LinkPurpose, subscriber_id: Uuid) -> String {
+        format!(
+            "{}{}?subscriber_id={}&token={}",
+            self.base_url,
+            purpose.path(),
+            subscriber_id,
+            self.token(purpose, subscriber_id)
+        )
+    }
+
+    /// The hex-encoded tag for a subscriber and purpose.
+    pub fn token(&self, purpose: LinkPurpose, subscriber_id: Uuid) -> String {
+        hex::encode(self.mac(purpose, subscriber_id).finalize().into_bytes())
+    }
+
+    /// Check a token in constant time.
+    pub fn verify(&self, purpose: LinkPurpose, subscriber_id: Uuid, token: &str) -> bool {
+        match hex::decode(token) {
+            Ok(tag) => self.mac(purpose, subscriber_id).verify_slice(&tag).is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    fn mac(&self, purpose: LinkPurpose, subscriber_id: Uuid) -> Hmac<sha2::Sha256> {
+        let mut mac =
+            Hmac::<sha2::Sha256>::new_from_slice(self.hmac_secret.expose_secret().as_bytes())
+                .expect("HMAC accepts keys of any length");
+        mac.update(purpose.as_str().as_bytes());
+        mac.update(b":");
+        mac.update(subscriber_id.as_bytes());
+        mac
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LinkPurpose, SubscriberLinks};
+    use secrecy::Secret;
+    use uuid::Uuid;
+
+    fn links(secret: &str) -> SubscriberLinks {
+        SubscriberLinks::new("http://127.0.0.1".into(), Secret::new(secret.into()))
+    }
+
+    #[test]
+    fn a_token_is_valid_for_the_subscriber_it_was_issued_for() {
+        let links = links("secret");
+        let subscriber_id = Uuid::new_v4();
+        let token = links.token(LinkPurpose::Unsubscribe, subscriber_id);
+        assert!(links.verify(LinkPurpose::Unsubscribe, subscriber_id, &token));
+    }
+
+    #[test]
+    fn a_token_is_rejected_for_another_subscriber() {
+        let links = links("secret");
+        let token = links.token(LinkPurpose::Unsubscribe, Uuid::new_v4());
+        assert!(!links.verify(LinkPurpose::Unsubscribe, Uuid::new_v4(), &token));
+    }
+
+    #[test]
+    fn a_token_signed_with_another_secret_is_rejected() {
+        let subscriber_id = Uuid::new_v4();
+        let token = links("another-secret").token(LinkPurpose::Unsubscribe, subscriber_id);
+        assert!(!links("secret").verify(LinkPurpose::Unsubscribe, subscriber_id, &token));
+    }
+
+    #[test]
+    fn a_token_is_rejected_for_another_purpose() {
+        let links = links("secret");
+        let subscriber_id = Uuid::new_v4();
+        let token = links.token(LinkPurpose::Preferences, subscriber_id);
+        assert!(!links.verify(LinkPurpose::Unsubscribe, subscriber_id, &token));
+    }
+
+    #[test]
+    fn malformed_tokens_are_rejected() {
+        let links = links("secret");
+        let subscriber_id = Uuid::new_v4();
+        for token in &["", "not-hex", "abcd"] {
+            assert!(!links.verify(LinkPurpose::Unsubscribe, subscriber_id, token));
+        }
+    }
+
+    #[test]
+    fn the_link_embeds_the_subscriber_id_and_token() {
+        let links = links("secret");
+        let subscriber_id = Uuid::new_v4();
+        let link = links.unsubscribe_link(subscriber_id);
+        assert_eq!(
+            link,
+            format!(
+                "http://127.0.0.1/subscriptions/unsubscribe?subscriber_id={}&token={}",
+                subscriber_id,
+                links.token(LinkPurpose::Unsubscribe, subscriber_id)
+            )
+        );
+    }
+}
@@ -1,6 +1,21 @@
-use tracing::{subscriber::set_global_default, Subscriber};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::Error;
+use opentelemetry::global;
+use opentelemetry::propagation::{Extractor, Injector};
+use opentelemetry::trace::TraceError;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::Tracer;
+use opentelemetry_sdk::Resource;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::{subscriber::set_global_default, Span, Subscriber};
+use tracing_actix_web::{root_span, DefaultRootSpanBuilder, RootSpanBuilder};
 use tracing_bunyan_formatter::{BunyanFormattingLayer, JsonStorageLayer};
 use tracing_log::LogTracer;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
 use tracing_subscriber::{fmt::MakeWriter, layer::SubscriberExt, EnvFilter, Registry};
 
 /// Compose multiple layers into a `tracing`'s subscriber.
@@ -13,10 +28,14 @@ use tracing_subscriber::{fmt::MakeWriter, layer::SubscriberExt, EnvFilter, Regis
 /// We need to explicitly call out that the returned subscriber is
 /// `Send` and `Sync` to make it possible to pass it to `init_subscriber`
 /// later on.
+///
+/// Spans are also handed to `tracer`, when there is one, to be exported
+/// over OpenTelemetry.
 pub fn get_subscriber<Sink>(
     name: String,
     env_filter: String,
     sink: Sink,
+    tracer: Option<Tracer>,
 ) -> impl Subscriber + Sync + Send
 where
     // This "weird" syntax is a higher-ranked trait bound (HRTB)
@@ -33,6 +52,7 @@ where
         .with(env_filter)
         .with(JsonStorageLayer)
         .with(formatting_layer)
+        .with(tracer.map(|tracer| tracing_opentelemetry::layer().with_tracer(tracer)))
 }
 
 /// Register a subscriber as global default to process span data.
@@ -41,4 +61,150 @@ where
 pub fn init_subscriber(subscriber: impl Subscriber + Send + Sync) {
     LogTracer::init().expect("Failed to set logger");
     set_global_default(subscriber).expect("Failed to set subscriber");
+    global::set_text_map_propagator(TraceContextPropagator::new());
+}
+
+/// Export spans in batches to an OpenTelemetry collector, over OTLP/HTTP.
+///
+/// `endpoint` is the collector's base URL, e.g. `http://localhost:4318`:
+/// spans are posted to `/v1/traces` under it. The tracer provider is
+/// installed globally, so that `opentelemetry::global::shutdown_tracer_provider`
+/// can flush whatever is still buffered on the way out.
+pub fn otlp_tracer(
+    endpoint: &str,
+    service_name: &str,
+    timeout: Duration,
+) -> Result<Tracer, TraceError> {
+    let http_client = reqwest::Client::builder()
+        .timeout(timeout)
+        .build()
+        .map_err(|e| TraceError::Other(Box::new(e)))?;
+    let exporter = opentelemetry_otlp::new_exporter()
+        .http()
+        .with_http_client(http_client)
+        .with_endpoint(endpoint)
+        .with_timeout(timeout);
+    opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(exporter)
+        .with_trace_config(
+            opentelemetry_sdk::trace::config().with_resource(Resource::new(vec![KeyValue::new(
+                "service.name",
+                service_name.to_owned(),
+            )])),
+        )
+        .install_batch(opentelemetry_sdk::runtime::Tokio)
+}
+
+/// The root span of every request, made a child of the caller's trace when
+/// the request carries a W3C `traceparent` header.
+pub struct TraceContextRootSpan;
+
+impl RootSpanBuilder for TraceContextRootSpan {
+    fn on_request_start(request: &ServiceRequest) -> Span {
+        let span = root_span!(request);
+        let parent_context = global::get_text_map_propagator(|propagator| {
+            propagator.extract(&RequestHeaders(request.headers()))
+        });
+        span.set_parent(parent_context);
+        span
+    }
+
+    fn on_request_end<B>(span: Span, outcome: &Result<ServiceResponse<B>, Error>) {
+        DefaultRootSpanBuilder::on_request_end(span, outcome)
+    }
+}
+
+struct RequestHeaders<'a>(&'a actix_web::http::header::HeaderMap);
+
+impl<'a> Extractor for RequestHeaders<'a> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|name| name.as_str()).collect()
+    }
+}
+
+/// Headers carrying the current span's trace context, to be sent along
+/// with an outgoing request. Empty if spans are not exported.
+pub fn trace_context_headers() -> HeaderMap {
+    let context = Span::current().context();
+    let mut headers = HeaderMap::new();
+    global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&context, &mut OutgoingHeaders(&mut headers))
+    });
+    headers
+}
+
+struct OutgoingHeaders<'a>(&'a mut HeaderMap);
+
+impl<'a> Injector for OutgoingHeaders<'a> {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(key.as_bytes()),
+            HeaderValue::from_str(&value),
+        ) {
+            self.0.insert(name, value);
+        }
+    }
+}
+
+/// Run a CPU-intensive closure on the blocking thread pool, keeping it
+/// attached to the span that was current when it was scheduled.
+pub fn spawn_blocking_with_tracing<F, R>(f: F) -> JoinHandle<R>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    let current_span = tracing::Span::current();
+    tokio::task::spawn_blocking(move || current_span.in_scope(f))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::otlp_tracer;
+    use std::time::Duration;
+    use tracing_subscriber::{layer::SubscriberExt, Registry};
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    // The batch exporter runs on the Tokio runtime, while flushing blocks:
+    // they need threads of their own.
+    #[tokio::test(flavor = "multi_thread")]
+    async fn spans_are_exported_to_the_collector() {
+        // Arrange
+        let collector = MockServer::start().await;
+        Mock::given(path("/v1/traces"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1..)
+            .mount(&collector)
+            .await;
+        let tracer = otlp_tracer(&collector.uri(), "zero2prod-test", Duration::from_secs(2))
+            .expect("Failed to build the tracer.");
+        let provider = tracer.provider().unwrap();
+        let subscriber =
+            Registry::default().with(tracing_opentelemetry::layer().with_tracer(tracer));
+
+        // Act
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("Adding a new subscriber").in_scope(|| {
+                tracing::info_span!("Saving new subscriber details in the database")
+                    .in_scope(|| {});
+            });
+        });
+        tokio::task::spawn_blocking(move || provider.force_flush())
+            .await
+            .unwrap();
+
+        // Assert
+        let requests = collector.received_requests().await.unwrap();
+        let exported: Vec<u8> = requests.into_iter().flat_map(|r| r.body).collect();
+        let exported = String::from_utf8_lossy(&exported);
+        assert!(exported.contains("zero2prod-test"));
+        assert!(exported.contains("Adding a new subscriber"));
+        assert!(exported.contains("Saving new subscriber details in the database"));
+    }
 }
@@ -0,0 +1,73 @@
+use crate::configuration::Settings;
+use crate::idempotency::delete_expired_responses;
+use crate::rate_limit::delete_expired_counters;
+use crate::session_store::delete_expired_sessions;
+use crate::shutdown::Shutdown;
+use crate::startup::get_connection_pool;
+use chrono::Utc;
+use sqlx::PgPool;
+use std::time::Duration;
+
+/// Delete the unused subscription tokens issued more than `ttl` ago.
+///
+/// Expired tokens are rejected by `routes::confirm` anyway. Consumed ones are
+/// kept, whatever their age, so that clicking a confirmation link again
+/// stays a no-op instead of failing.
+#[tracing::instrument(skip(pool), err)]
+pub async fn delete_stale_tokens(
+    pool: &PgPool,
+    ttl: chrono::Duration,
+) -> Result<u64, anyhow::Error> {
+    let n_deleted = sqlx::query!(
+        r#"
+        DELETE FROM subscription_tokens
+        WHERE created_at < $1 AND consumed_at IS NULL
+        "#,
+        Utc::now() - ttl
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+    Ok(n_deleted)
+}
+
+async fn cleanup_loop(
+    pool: PgPool,
+    token_ttl: chrono::Duration,
+    idempotency_ttl: chrono::Duration,
+    interval: Duration,
+    shutdown: Shutdown,
+) -> Result<(), anyhow::Error> {
+    while !shutdown.is_triggered() {
+        if let Ok(n_deleted) = delete_stale_tokens(&pool, token_ttl).await {
+            tracing::info!("Deleted {} stale subscription tokens", n_deleted);
+        }
+        if let Ok(n_deleted) = delete_expired_counters(&pool).await {
+            tracing::info!("Deleted {} expired rate limit counters", n_deleted);
+        }
+        if let Ok(n_deleted) = delete_expired_sessions(&pool).await {
+            tracing::info!("Deleted {} expired sessions", n_deleted);
+        }
+        if let Ok(n_deleted) = delete_expired_responses(&pool, idempotency_ttl).await {
+            tracing::info!("Deleted {} expired idempotency keys", n_deleted);
+        }
+        shutdown.sleep(interval).await;
+    }
+    pool.close().await;
+    Ok(())
+}
+
+pub async fn run_cleanup_until_stopped(
+    configuration: Settings,
+    shutdown: Shutdown,
+) -> Result<(), anyhow::Error> {
+    let connection_pool = get_connection_pool(&configuration.database);
+    cleanup_loop(
+        connection_pool,
+        configuration.subscription_tokens.ttl(),
+        configuration.idempotency.ttl(),
+        configuration.subscription_tokens.cleanup_interval(),
+        shutdown,
+    )
+    .await
+}
@@ -0,0 +1,169 @@
+use crate::helpers::{spawn_app, spawn_app_with, TestApp};
+use sha2::{Digest, Sha256};
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+
+const EMAIL: &str = "ursula_le_guin@gmail.com";
+
+async fn get_challenge(app: &TestApp) -> serde_json::Value {
+    app.api_client
+        .get(format!("{}/subscriptions/challenge", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json()
+        .await
+        .unwrap()
+}
+
+fn solves(challenge: &str, email: &str, nonce: &str, difficulty: u64) -> bool {
+    let hash = Sha256::digest(format!("{}:{}:{}", challenge, email, nonce).as_bytes());
+    let mut zero_bits = 0;
+    for byte in hash.iter() {
+        zero_bits += byte.leading_zeros() as u64;
+        if *byte != 0 {
+            break;
+        }
+    }
+    zero_bits >= difficulty
+}
+
+/// Brute-force a nonce, the way the sign-up page does.
+fn solve(challenge: &str, email: &str, difficulty: u64) -> String {
+    (0u64..)
+        .map(|n| n.to_string())
+        .find(|nonce| solves(challenge, email, nonce, difficulty))
+        .unwrap()
+}
+
+async fn assert_nothing_was_stored(app: &TestApp) {
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(saved.is_empty());
+}
+
+#[tokio::test]
+async fn sign_ups_filling_in_the_honeypot_are_silently_dropped() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com&website=http%3A%2F%2Fspam.example";
+
+    // Act
+    let response = app.post_subscriptions(body.into()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_nothing_was_stored(&app).await;
+}
+
+#[tokio::test]
+async fn a_solved_challenge_lets_the_sign_up_through() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.bot_protection.proof_of_work_required = true;
+        c.bot_protection.difficulty_bits = 8;
+    })
+    .await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let challenge = get_challenge(&app).await;
+    let challenge_str = challenge["challenge"].as_str().unwrap();
+    let nonce = solve(
+        challenge_str,
+        EMAIL,
+        challenge["difficulty"].as_u64().unwrap(),
+    );
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .form(&[
+            ("name", "le guin"),
+            ("email", EMAIL),
+            ("challenge", challenge_str),
+            ("nonce", &nonce),
+        ])
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.email, EMAIL);
+}
+
+#[tokio::test]
+async fn sign_ups_without_a_valid_solution_are_silently_dropped() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.bot_protection.proof_of_work_required = true;
+        c.bot_protection.difficulty_bits = 8;
+    })
+    .await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let challenge = get_challenge(&app).await;
+    let challenge_str = challenge["challenge"].as_str().unwrap();
+    // A nonce can solve the challenge for both addresses by chance.
+    let nonce_for_another_address = (0u64..)
+        .map(|n| n.to_string())
+        .find(|nonce| {
+            solves(challenge_str, "someone_else@gmail.com", nonce, 8)
+                && !solves(challenge_str, EMAIL, nonce, 8)
+        })
+        .unwrap();
+    let test_cases = vec![
+        (vec![], "no challenge"),
+        (
+            vec![("challenge", "1654956000.abcdef.0123"), ("nonce", "1")],
+            "a forged challenge",
+        ),
+        (
+            vec![
+                ("challenge", challenge_str),
+                ("nonce", &nonce_for_another_address),
+            ],
+            "a solution for another address",
+        ),
+    ];
+
+    for (extra_fields, description) in test_cases {
+        // Act
+        let mut form = vec![("name", "le guin"), ("email", EMAIL)];
+        form.extend(extra_fields);
+        let response = app
+            .api_client
+            .post(format!("{}/subscriptions", &app.address))
+            .form(&form)
+            .send()
+            .await
+            .unwrap();
+
+        // Assert
+        assert_eq!(
+            response.status().as_u16(),
+            200,
+            "The API did not look like it accepted a sign-up with {}.",
+            description
+        );
+    }
+    assert_nothing_was_stored(&app).await;
+}
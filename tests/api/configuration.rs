@@ -0,0 +1,22 @@
+use zero2prod::configuration::get_configuration;
+use zero2prod::startup::Application;
+
+#[tokio::test]
+async fn the_application_refuses_to_start_with_an_invalid_configuration() {
+    // Arrange
+    let mut configuration = get_configuration().expect("Failed to read configuration.");
+    configuration.application.port = 0;
+    configuration.email_client.sender_email = "not-an-email".into();
+    configuration.application.hmac_secret = secrecy::Secret::new("short".into());
+
+    // Act
+    let outcome = Application::build(configuration).await;
+
+    // Assert
+    let error = match outcome {
+        Ok(_) => panic!("The application started with an invalid configuration."),
+        Err(e) => e.to_string(),
+    };
+    assert!(error.contains("application.hmac_secret"));
+    assert!(error.contains("email_client.sender_email"));
+}
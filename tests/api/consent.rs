@@ -0,0 +1,184 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use crate::newsletters::create_confirmed_subscriber;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+const EMAIL: &str = "ursula_le_guin@gmail.com";
+
+async fn log_in(app: &TestApp) {
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password
+    }))
+    .await;
+}
+
+async fn get_subscriber_detail(app: &TestApp, email: &str) -> reqwest::Response {
+    app.api_client
+        .get(format!("{}/admin/subscribers", &app.address))
+        .query(&[("email", email)])
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[tokio::test]
+async fn sign_up_and_confirmation_each_leave_a_consent_record() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("User-Agent", "test-browser/1.0")
+        .form(&[("name", "le guin"), ("email", EMAIL)])
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    reqwest::Client::new()
+        .get(confirmation_links.html)
+        .header("User-Agent", "mail-client/2.0")
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    let records = sqlx::query!(
+        r#"
+        SELECT event, ip_address, user_agent, source, privacy_policy_version
+        FROM consent_records
+        ORDER BY recorded_at
+        "#
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0].event, "sign_up");
+    assert_eq!(records[0].source, "subscription_form");
+    assert_eq!(records[0].user_agent.as_deref(), Some("test-browser/1.0"));
+    assert_eq!(records[1].event, "confirmation");
+    assert_eq!(records[1].source, "confirmation_link");
+    assert_eq!(records[1].user_agent.as_deref(), Some("mail-client/2.0"));
+    for record in &records {
+        assert_eq!(record.ip_address.as_deref(), Some("127.0.0.1"));
+        assert_eq!(record.privacy_policy_version, "2022-05-01");
+    }
+}
+
+#[tokio::test]
+async fn the_sign_up_source_can_be_set_by_the_form() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com&source=footer_widget";
+
+    // Act
+    app.post_subscriptions(body.into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    let record = sqlx::query!("SELECT source FROM consent_records")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(record.source, "footer_widget");
+}
+
+#[tokio::test]
+async fn consent_records_are_part_of_the_export_and_lose_identifiers_on_erasure() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    log_in(&app).await;
+
+    // Act - Part 1 - Export
+    let export: serde_json::Value = app
+        .api_client
+        .get(format!("{}/admin/subscribers/export", &app.address))
+        .query(&[("email", EMAIL)])
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    // Assert - Part 1
+    let records = export["consent_records"].as_array().unwrap();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0]["event"], "sign_up");
+    assert_eq!(records[0]["ip_address"], "127.0.0.1");
+
+    // Act - Part 2 - Erase
+    app.api_client
+        .post(format!("{}/admin/subscribers/erase", &app.address))
+        .json(&serde_json::json!({ "email": EMAIL }))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Assert - Part 2
+    let records = sqlx::query!("SELECT ip_address, user_agent FROM consent_records")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(records.len(), 2);
+    for record in records {
+        assert!(record.ip_address.is_none());
+        assert!(record.user_agent.is_none());
+    }
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_a_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = get_subscriber_detail(&app, EMAIL).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn the_subscriber_page_lists_their_consent_records() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    log_in(&app).await;
+
+    // Act
+    let response = get_subscriber_detail(&app, EMAIL).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains(EMAIL));
+    assert!(html_page.contains("<td>sign_up</td>"));
+    assert!(html_page.contains("<td>confirmation</td>"));
+    assert!(html_page.contains("<td>2022-05-01</td>"));
+
+    let response = get_subscriber_detail(&app, "someone_else@example.com").await;
+    assert_eq!(response.status().as_u16(), 404);
+}
@@ -1,4 +1,7 @@
-use crate::helpers::spawn_app;
+use crate::helpers::{spawn_app, spawn_app_with, TestApp};
+use sqlx::{Connection, Executor, PgConnection};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
 
 #[tokio::test]
 async fn health_check_works() {
@@ -9,7 +12,7 @@ async fn health_check_works() {
     // Act
     let response = client
         // Use the returned application address
-        .get(&format!("{}/health_check", &app.address))
+        .get(format!("{}/health_check", &app.address))
         .send()
         .await
         .expect("Failed to execute request.");
@@ -18,3 +21,126 @@ async fn health_check_works() {
     assert!(response.status().is_success());
     assert_eq!(Some(0), response.content_length());
 }
+
+async fn get_readiness(app: &TestApp) -> (u16, serde_json::Value) {
+    let response = app
+        .api_client
+        .get(format!("{}/health/ready", &app.address))
+        .send()
+        .await
+        .unwrap();
+    let status = response.status().as_u16();
+    (status, response.json().await.unwrap())
+}
+
+#[tokio::test]
+async fn liveness_does_not_depend_on_the_database() {
+    // Arrange
+    let app = spawn_app().await;
+    drop_database(&app).await;
+
+    // Act
+    let response = app
+        .api_client
+        .get(format!("{}/health/live", &app.address))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn readiness_reports_every_dependency() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let (status, body) = get_readiness(&app).await;
+
+    // Assert
+    assert_eq!(status, 200);
+    assert_eq!(body["status"], "ready");
+    assert_eq!(body["checks"]["database"]["status"], "up");
+    assert!(body["checks"]["database"]["latency_ms"].is_u64());
+    assert_eq!(body["checks"]["migrations"]["status"], "up");
+    assert!(body["checks"].get("email_provider").is_none());
+}
+
+#[tokio::test]
+async fn readiness_fails_when_the_database_is_unreachable() {
+    // Arrange
+    let app = spawn_app().await;
+    drop_database(&app).await;
+
+    // Act
+    let (status, body) = get_readiness(&app).await;
+
+    // Assert
+    assert_eq!(status, 503);
+    assert_eq!(body["status"], "not_ready");
+    assert_eq!(body["checks"]["database"]["status"], "down");
+}
+
+#[tokio::test]
+async fn readiness_fails_when_a_migration_is_missing() {
+    // Arrange
+    let app = spawn_app().await;
+    sqlx::query(
+        "DELETE FROM _sqlx_migrations \
+         WHERE version = (SELECT max(version) FROM _sqlx_migrations)",
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    let (status, body) = get_readiness(&app).await;
+
+    // Assert
+    assert_eq!(status, 503);
+    assert_eq!(body["checks"]["database"]["status"], "up");
+    assert_eq!(body["checks"]["migrations"]["status"], "down");
+}
+
+#[tokio::test]
+async fn readiness_can_depend_on_the_email_provider() {
+    // Arrange
+    let app = spawn_app_with(|c| c.health.check_email_provider = true).await;
+    Mock::given(path("/server"))
+        .and(method("GET"))
+        .respond_with(ResponseTemplate::new(200))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - The provider answers
+    let (status, body) = get_readiness(&app).await;
+    assert_eq!(status, 200);
+    assert_eq!(body["checks"]["email_provider"]["status"], "up");
+
+    // Act - Part 2 - The provider fails
+    Mock::given(path("/server"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+    let (status, body) = get_readiness(&app).await;
+    assert_eq!(status, 503);
+    assert_eq!(body["checks"]["email_provider"]["status"], "down");
+}
+
+/// Take the database of the application away from under its feet.
+async fn drop_database(app: &TestApp) {
+    let mut connection = PgConnection::connect_with(&app.configuration.database.without_db())
+        .await
+        .unwrap();
+    connection
+        .execute(&*format!(
+            r#"DROP DATABASE "{}" WITH (FORCE);"#,
+            app.configuration.database.database_name
+        ))
+        .await
+        .unwrap();
+}
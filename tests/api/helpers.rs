@@ -1,29 +1,95 @@
 use once_cell::sync::Lazy;
+use opentelemetry::trace::TracerProvider;
+use secrecy::{ExposeSecret, Secret};
 use sqlx::{Connection, Executor, PgConnection, PgPool};
+use std::sync::Arc;
+use tokio::task::JoinHandle;
 use uuid::Uuid;
 use wiremock::MockServer;
-use zero2prod::configuration::{get_configuration, DatabaseSettings};
+use zero2prod::authentication::compute_password_hash;
+use zero2prod::configuration::{get_configuration, DatabaseSettings, EmailTransport, Settings};
+use zero2prod::email_client::EmailClient;
+use zero2prod::email_templates::EmailTemplates;
+use zero2prod::issue_delivery_worker::{try_execute_task, ExecutionOutcome};
+use zero2prod::metrics::Metrics;
+use zero2prod::shutdown::Shutdown;
 use zero2prod::startup::{get_connection_pool, Application};
+use zero2prod::subscriber_links::SubscriberLinks;
 use zero2prod::telemetry::{get_subscriber, init_subscriber};
 
 // Ensure that the `tracing` stack is only initialised once using `once_cell`
 static TRACING: Lazy<()> = Lazy::new(|| {
     let default_filter_level = "info".to_string();
     let subscriber_name = "test".to_string();
+    // Spans get OpenTelemetry trace ids, so that we can check how they are
+    // propagated, but are not exported anywhere.
+    let provider = opentelemetry_sdk::trace::TracerProvider::builder().build();
+    let tracer = Some(provider.tracer("test"));
+    opentelemetry::global::set_tracer_provider(provider);
     if std::env::var("TEST_LOG").is_ok() {
-        let subscriber = get_subscriber(subscriber_name, default_filter_level, std::io::stdout);
+        let subscriber = get_subscriber(
+            subscriber_name,
+            default_filter_level,
+            std::io::stdout,
+            tracer,
+        );
         init_subscriber(subscriber);
     } else {
-        let subscriber = get_subscriber(subscriber_name, default_filter_level, std::io::sink);
+        let subscriber =
+            get_subscriber(subscriber_name, default_filter_level, std::io::sink, tracer);
         init_subscriber(subscriber);
     };
 });
 
+pub struct TestUser {
+    pub user_id: Uuid,
+    pub username: String,
+    pub password: String,
+}
+
+impl TestUser {
+    pub fn generate() -> Self {
+        Self {
+            user_id: Uuid::new_v4(),
+            username: Uuid::new_v4().to_string(),
+            password: Uuid::new_v4().to_string(),
+        }
+    }
+
+    async fn store(&self, pool: &PgPool) {
+        let password_hash = compute_password_hash(Secret::new(self.password.clone())).unwrap();
+        sqlx::query!(
+            "INSERT INTO users (user_id, username, password_hash) VALUES ($1, $2, $3)",
+            self.user_id,
+            self.username,
+            password_hash.expose_secret(),
+        )
+        .execute(pool)
+        .await
+        .expect("Failed to store test user.");
+    }
+}
+
 pub struct TestApp {
     pub address: String,
     pub port: u16,
+    /// Set when `/metrics` is served on a port of its own.
+    pub admin_port: Option<u16>,
     pub db_pool: PgPool,
     pub email_server: MockServer,
+    pub test_user: TestUser,
+    pub api_client: reqwest::Client,
+    pub email_client: Arc<dyn EmailClient>,
+    pub email_templates: EmailTemplates,
+    pub subscriber_links: SubscriberLinks,
+    /// What the application was started with.
+    pub configuration: Settings,
+    /// Those of the application, for a background worker to share.
+    pub metrics: Arc<Metrics>,
+    /// Stops the application gracefully.
+    pub shutdown: Shutdown,
+    /// Completes once the application has stopped.
+    pub server_task: JoinHandle<Result<(), std::io::Error>>,
 }
 
 /// Confirmation links embedded in the request to the email API.
@@ -32,10 +98,51 @@ pub struct ConfirmationLinks {
     pub plain_text: reqwest::Url,
 }
 
+/// Unsubscribe links embedded in a newsletter issue.
+pub struct UnsubscribeLinks {
+    pub html: reqwest::Url,
+    pub plain_text: reqwest::Url,
+}
+
+/// Preference center links embedded in every email.
+pub struct PreferencesLinks {
+    pub html: reqwest::Url,
+    pub plain_text: reqwest::Url,
+}
+
 impl TestApp {
+    /// Run the delivery worker until the queue holds no task that is due.
+    pub async fn dispatch_all_pending_emails(&self) {
+        loop {
+            if let ExecutionOutcome::EmptyQueue = try_execute_task(
+                &self.db_pool,
+                self.email_client.as_ref(),
+                &self.email_templates,
+                &self.subscriber_links,
+            )
+            .await
+            .unwrap()
+            {
+                break;
+            }
+        }
+    }
+
+    /// Create a mailing list next to the default one.
+    pub async fn create_list(&self, slug: &str) {
+        sqlx::query!(
+            "INSERT INTO lists (list_id, slug, name) VALUES ($1, $2, $2)",
+            Uuid::new_v4(),
+            slug
+        )
+        .execute(&self.db_pool)
+        .await
+        .expect("Failed to create list.");
+    }
+
     pub async fn post_subscriptions(&self, body: String) -> reqwest::Response {
-        reqwest::Client::new()
-            .post(&format!("{}/subscriptions", &self.address))
+        self.api_client
+            .post(format!("{}/subscriptions", &self.address))
             .header("Content-Type", "application/x-www-form-urlencoded")
             .body(body)
             .send()
@@ -43,8 +150,108 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    pub async fn post_subscriptions_json(&self, body: serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/subscriptions", &self.address))
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_newsletters(&self, body: serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/newsletters", &self.address))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_newsletters_with_idempotency_key(
+        &self,
+        body: serde_json::Value,
+        idempotency_key: &str,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/newsletters", &self.address))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .header("Idempotency-Key", idempotency_key)
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_login<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/login", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_login_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/login", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_admin_dashboard(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/dashboard", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin_dashboard_html(&self) -> String {
+        self.get_admin_dashboard().await.text().await.unwrap()
+    }
+
+    pub async fn post_logout(&self) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/logout", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     /// Extract the confirmation links embedded in the request to the email API.
     pub fn get_confirmation_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
+        let (html, plain_text) = self.get_links(email_request, "/subscriptions/confirm");
+        ConfirmationLinks { html, plain_text }
+    }
+
+    /// Extract the unsubscribe links embedded in a newsletter issue.
+    pub fn get_unsubscribe_links(&self, email_request: &wiremock::Request) -> UnsubscribeLinks {
+        let (html, plain_text) = self.get_links(email_request, "/subscriptions/unsubscribe");
+        UnsubscribeLinks { html, plain_text }
+    }
+
+    /// Extract the preference center links embedded in an email.
+    pub fn get_preferences_links(&self, email_request: &wiremock::Request) -> PreferencesLinks {
+        let (html, plain_text) = self.get_links(email_request, "/subscriptions/preferences");
+        PreferencesLinks { html, plain_text }
+    }
+
+    /// Extract the only link to `path` in the HTML and in the plain text body
+    /// of an email.
+    fn get_links(
+        &self,
+        email_request: &wiremock::Request,
+        path: &str,
+    ) -> (reqwest::Url, reqwest::Url) {
         let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
 
         // Extract the link from one of the request fields.
@@ -52,6 +259,7 @@ impl TestApp {
             let links: Vec<_> = linkify::LinkFinder::new()
                 .links(s)
                 .filter(|l| *l.kind() == linkify::LinkKind::Url)
+                .filter(|l| reqwest::Url::parse(l.as_str()).unwrap().path() == path)
                 .collect();
             assert_eq!(links.len(), 1);
             let raw_link = links[0].as_str().to_owned();
@@ -62,13 +270,20 @@ impl TestApp {
             confirmation_link
         };
 
-        let html = get_link(body["HtmlBody"].as_str().unwrap());
+        // Links are HTML-escaped in the HTML body, like any other variable.
+        let html_body = htmlescape::decode_html(body["HtmlBody"].as_str().unwrap()).unwrap();
+        let html = get_link(&html_body);
         let plain_text = get_link(body["TextBody"].as_str().unwrap());
-        ConfirmationLinks { html, plain_text }
+        (html, plain_text)
     }
 }
 
 pub async fn spawn_app() -> TestApp {
+    spawn_app_with(|_| {}).await
+}
+
+/// Like `spawn_app`, with a chance to adjust the configuration first.
+pub async fn spawn_app_with(customize: impl FnOnce(&mut Settings)) -> TestApp {
     Lazy::force(&TRACING);
 
     // Launch a mock server to stand in for Postmark's API
@@ -82,7 +297,9 @@ pub async fn spawn_app() -> TestApp {
         // Use a random OS port
         c.application.port = 0;
         // Use the mock server as email API
+        c.email_client.transport = EmailTransport::Postmark;
         c.email_client.base_url = email_server.uri();
+        customize(&mut c);
         c
     };
 
@@ -94,14 +311,38 @@ pub async fn spawn_app() -> TestApp {
         .await
         .expect("Failed to build application.");
     let application_port = application.port();
-    let _ = tokio::spawn(application.run_until_stopped());
+    let admin_port = application.admin_port();
+    let metrics = application.metrics();
+    let shutdown = application.shutdown();
+    let server_task = tokio::spawn(application.run_until_stopped());
+
+    let api_client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .cookie_store(true)
+        .build()
+        .unwrap();
 
-    TestApp {
+    let test_app = TestApp {
+        configuration: configuration.clone(),
         address: format!("http://localhost:{}", application_port),
         port: application_port,
+        admin_port,
         db_pool: get_connection_pool(&configuration.database),
         email_server,
-    }
+        test_user: TestUser::generate(),
+        api_client,
+        email_client: configuration.email_client.client().unwrap(),
+        email_templates: EmailTemplates::load(&configuration.email_templates).unwrap(),
+        subscriber_links: SubscriberLinks::new(
+            configuration.application.base_url,
+            configuration.application.hmac_secret,
+        ),
+        metrics,
+        shutdown,
+        server_task,
+    };
+    test_app.test_user.store(&test_app.db_pool).await;
+    test_app
 }
 
 async fn configure_database(config: &DatabaseSettings) -> PgPool {
@@ -125,3 +366,8 @@ async fn configure_database(config: &DatabaseSettings) -> PgPool {
 
     connection_pool
 }
+
+pub fn assert_is_redirect_to(response: &reqwest::Response, location: &str) {
+    assert_eq!(response.status().as_u16(), 303);
+    assert_eq!(response.headers().get("Location").unwrap(), location);
+}
@@ -0,0 +1,221 @@
+use crate::helpers::{spawn_app, TestApp};
+use crate::newsletters::create_confirmed_subscriber;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::issue_delivery_worker::{try_execute_task, ExecutionOutcome};
+
+fn newsletter_request_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    })
+}
+
+#[tokio::test]
+async fn publishing_enqueues_one_task_per_confirmed_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    // Act
+    app.post_newsletters(newsletter_request_body())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    let saved = sqlx::query!("SELECT subscriber_email FROM issue_delivery_queue")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.len(), 1);
+    assert_eq!(saved[0].subscriber_email, "ursula_le_guin@gmail.com");
+}
+
+#[tokio::test]
+async fn a_delivered_task_is_removed_from_the_queue() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.post_newsletters(newsletter_request_body())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let outcome = try_execute_task(
+        &app.db_pool,
+        app.email_client.as_ref(),
+        &app.email_templates,
+        &app.subscriber_links,
+    )
+    .await
+    .unwrap();
+
+    // Assert
+    assert!(matches!(outcome, ExecutionOutcome::TaskCompleted));
+    let outcome = try_execute_task(
+        &app.db_pool,
+        app.email_client.as_ref(),
+        &app.email_templates,
+        &app.subscriber_links,
+    )
+    .await
+    .unwrap();
+    assert!(matches!(outcome, ExecutionOutcome::EmptyQueue));
+}
+
+#[tokio::test]
+async fn a_failed_delivery_is_postponed_instead_of_dropped() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.post_newsletters(newsletter_request_body())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let saved = sqlx::query!(
+        "SELECT n_retries, execute_after > now() AS \"postponed!\" FROM issue_delivery_queue"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("The task should still be queued.");
+    assert_eq!(saved.n_retries, 1);
+    assert!(saved.postponed);
+}
+
+async fn publish_issue(app: &TestApp, title: &str) {
+    app.post_newsletters(serde_json::json!({
+        "title": title,
+        "content": {
+            "text": format!("{} as plain text", title),
+            "html": format!("<p>{} as HTML</p>", title),
+        }
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+}
+
+async fn set_digest_frequency(app: &TestApp, digest_frequency: &str) {
+    sqlx::query!(
+        "UPDATE subscriptions SET digest_frequency = $1",
+        digest_frequency
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn issues_are_held_back_for_subscribers_who_want_a_digest() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    set_digest_frequency(&app, "daily").await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    publish_issue(&app, "First issue").await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let saved =
+        sqlx::query!("SELECT execute_after > now() AS \"postponed!\" FROM issue_delivery_queue")
+            .fetch_one(&app.db_pool)
+            .await
+            .expect("The task should still be queued.");
+    assert!(saved.postponed);
+}
+
+#[tokio::test]
+async fn due_issues_are_delivered_as_a_single_digest() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    set_digest_frequency(&app, "weekly").await;
+    publish_issue(&app, "First issue").await;
+    publish_issue(&app, "Second issue").await;
+    // The start of the week has come.
+    sqlx::query!("UPDATE issue_delivery_queue SET execute_after = now()")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let text = body["TextBody"].as_str().unwrap();
+    assert!(text.find("First issue").unwrap() < text.find("Second issue").unwrap());
+    let n_logged =
+        sqlx::query_scalar!("SELECT COUNT(*) FROM issue_delivery_log WHERE outcome = 'delivered'")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(n_logged, Some(2));
+}
+
+#[tokio::test]
+async fn subscribers_who_want_every_issue_get_one_email_per_issue() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    publish_issue(&app, "First issue").await;
+    publish_issue(&app, "Second issue").await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    // Mock verifies on Drop that we have sent two emails
+}
@@ -0,0 +1,113 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with};
+use secrecy::Secret;
+use zero2prod::authentication::compute_password_hash;
+use zero2prod::session_store::delete_expired_sessions;
+
+#[tokio::test]
+async fn an_error_flash_message_is_set_on_failure() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act - Part 1 - Try to login
+    let login_body = serde_json::json!({
+        "username": "random-username",
+        "password": "random-password"
+    });
+    let response = app.post_login(&login_body).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("<p><i>Authentication failed</i></p>"));
+
+    // Act - Part 3 - Reload the login page
+    let html_page = app.get_login_html().await;
+    assert!(!html_page.contains("Authentication failed"));
+}
+
+#[tokio::test]
+async fn redirect_to_admin_dashboard_after_login_success() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act - Part 1 - Login
+    let login_body = serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password
+    });
+    let response = app.post_login(&login_body).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains(&format!("Welcome {}", app.test_user.username)));
+}
+
+#[tokio::test]
+async fn sessions_are_persisted_in_postgres() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let login_body = serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password
+    });
+    app.post_login(&login_body).await;
+
+    // Assert
+    let saved = sqlx::query!("SELECT state FROM sessions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved session.");
+    assert_eq!(
+        saved.state["user_id"],
+        serde_json::json!(serde_json::to_string(&app.test_user.user_id).unwrap())
+    );
+}
+
+#[tokio::test]
+async fn expired_sessions_are_cleaned_up() {
+    // Arrange
+    let app = spawn_app().await;
+    let login_body = serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password
+    });
+    app.post_login(&login_body).await;
+    sqlx::query!("UPDATE sessions SET expires_at = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let n_deleted = delete_expired_sessions(&app.db_pool).await.unwrap();
+
+    // Assert
+    assert_eq!(n_deleted, 1);
+    let saved = sqlx::query!("SELECT session_key FROM sessions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(saved.is_empty());
+}
+
+#[tokio::test]
+async fn the_admin_account_is_seeded_from_the_configured_password_hash() {
+    // Arrange
+    let password = uuid::Uuid::new_v4().to_string();
+    let password_hash = compute_password_hash(Secret::new(password.clone())).unwrap();
+    let app = spawn_app_with(|c| c.admin.password_hash = Some(password_hash)).await;
+
+    // Act
+    let login_body = serde_json::json!({
+        "username": "admin",
+        "password": &password
+    });
+    let response = app.post_login(&login_body).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
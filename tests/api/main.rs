@@ -1,4 +1,19 @@
+mod admin_dashboard;
+mod bot_protection;
+mod configuration;
+mod consent;
 mod health_check;
 mod helpers;
+mod issue_delivery_worker;
+mod login;
+mod metrics;
+mod newsletters;
+mod openapi;
+mod rate_limits;
+mod shutdown;
+mod subscriber_data;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_preferences;
+mod subscriptions_unsubscribe;
+mod telemetry;
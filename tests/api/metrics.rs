@@ -0,0 +1,182 @@
+use crate::helpers::{spawn_app, spawn_app_with, TestApp};
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::issue_delivery_worker::run_worker_until_stopped;
+
+async fn get_metrics(app: &TestApp) -> String {
+    let response = app
+        .api_client
+        .get(format!("{}/metrics", &app.address))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    response.text().await.unwrap()
+}
+
+/// The line of `metrics` for a series, e.g. `subscriptions_total{outcome="created"}`.
+fn sample<'a>(metrics: &'a str, series: &str) -> Option<&'a str> {
+    metrics
+        .lines()
+        .find(|line| line.starts_with(series) && line[series.len()..].starts_with(' '))
+}
+
+#[tokio::test]
+async fn sign_ups_and_their_emails_are_counted() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    app.post_subscriptions("name=le%20guin&email=definitely-not-an-email".into())
+        .await;
+    let metrics = get_metrics(&app).await;
+
+    // Assert
+    assert_eq!(
+        sample(&metrics, r#"subscriptions_total{outcome="created"}"#),
+        Some(r#"subscriptions_total{outcome="created"} 1"#)
+    );
+    assert_eq!(
+        sample(
+            &metrics,
+            r#"subscriptions_total{outcome="validation_failure"}"#
+        ),
+        Some(r#"subscriptions_total{outcome="validation_failure"} 1"#)
+    );
+    assert_eq!(
+        sample(&metrics, r#"email_send_attempts_total{backend="postmark"}"#),
+        Some(r#"email_send_attempts_total{backend="postmark"} 1"#)
+    );
+    assert!(sample(
+        &metrics,
+        r#"email_send_duration_seconds_count{backend="postmark"}"#
+    )
+    .is_some());
+    assert!(sample(&metrics, r#"db_pool_connections{pool="api"}"#).is_some());
+}
+
+#[tokio::test]
+async fn requests_are_counted_by_route_and_status() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    app.api_client
+        .get(format!(
+            "{}/subscriptions/confirm?subscription_token=unknown",
+            &app.address
+        ))
+        .send()
+        .await
+        .unwrap();
+    let metrics = get_metrics(&app).await;
+
+    // Assert
+    assert_eq!(
+        sample(
+            &metrics,
+            r#"http_requests_total{method="GET",route="/subscriptions/confirm",status="401"}"#
+        ),
+        Some(r#"http_requests_total{method="GET",route="/subscriptions/confirm",status="401"} 1"#)
+    );
+    assert_eq!(
+        sample(
+            &metrics,
+            r#"subscription_confirmations_total{outcome="unknown_token"}"#
+        ),
+        Some(r#"subscription_confirmations_total{outcome="unknown_token"} 1"#)
+    );
+}
+
+#[tokio::test]
+async fn every_attempt_at_sending_an_email_is_counted() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.email_client.retry.max_attempts = 2;
+        c.email_client.retry.base_delay_milliseconds = 1;
+    })
+    .await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    let metrics = get_metrics(&app).await;
+
+    // Assert
+    assert_eq!(
+        sample(&metrics, r#"email_send_attempts_total{backend="postmark"}"#),
+        Some(r#"email_send_attempts_total{backend="postmark"} 2"#)
+    );
+    assert_eq!(
+        sample(&metrics, r#"email_send_failures_total{backend="postmark"}"#),
+        Some(r#"email_send_failures_total{backend="postmark"} 2"#)
+    );
+    assert_eq!(
+        sample(
+            &metrics,
+            r#"email_send_duration_seconds_count{backend="postmark"}"#
+        ),
+        Some(r#"email_send_duration_seconds_count{backend="postmark"} 2"#)
+    );
+}
+
+#[tokio::test]
+async fn the_worker_pool_is_reported_alongside_the_api_pool() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let worker = tokio::spawn(run_worker_until_stopped(
+        app.configuration.clone(),
+        app.metrics.clone(),
+        app.shutdown.clone(),
+    ));
+    let metrics = get_metrics(&app).await;
+
+    // Assert
+    assert!(sample(&metrics, r#"db_pool_connections{pool="api"}"#).is_some());
+    assert!(sample(&metrics, r#"db_pool_connections{pool="worker"}"#).is_some());
+    app.shutdown.trigger();
+    worker.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn metrics_can_be_moved_to_an_admin_port() {
+    // Arrange
+    let app = spawn_app_with(|c| c.application.admin_port = Some(0)).await;
+    let admin_port = app.admin_port.unwrap();
+
+    // Act
+    let public = app
+        .api_client
+        .get(format!("{}/metrics", &app.address))
+        .send()
+        .await
+        .unwrap();
+    let admin = app
+        .api_client
+        .get(format!("http://localhost:{}/metrics", admin_port))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(public.status().as_u16(), 404);
+    assert_eq!(admin.status().as_u16(), 200);
+    assert!(admin
+        .text()
+        .await
+        .unwrap()
+        .contains("# TYPE http_requests_total counter"));
+}
@@ -0,0 +1,47 @@
+use crate::helpers::{spawn_app, spawn_app_with};
+
+#[tokio::test]
+async fn the_committed_openapi_document_is_served() {
+    // Arrange
+    let app = spawn_app().await;
+    let committed: serde_json::Value = serde_json::from_str(
+        &std::fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/openapi.json")).unwrap(),
+    )
+    .unwrap();
+
+    // Act
+    let response = app
+        .api_client
+        .get(format!("{}/openapi.json", &app.address))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let served: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(served, committed);
+}
+
+#[tokio::test]
+async fn the_docs_page_is_only_served_when_enabled() {
+    for enabled in [true, false].iter().copied() {
+        // Arrange
+        let app = spawn_app_with(|c| c.application.api_docs_page = enabled).await;
+
+        // Act
+        let response = app
+            .api_client
+            .get(format!("{}/api-docs", &app.address))
+            .send()
+            .await
+            .unwrap();
+
+        // Assert
+        let expected = if enabled { 200 } else { 404 };
+        assert_eq!(response.status().as_u16(), expected);
+        if enabled {
+            assert!(response.text().await.unwrap().contains("/openapi.json"));
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use crate::helpers::spawn_app_with;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+#[tokio::test]
+async fn sign_ups_from_the_same_address_are_limited() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.rate_limits.per_ip.max_requests = 2;
+    })
+    .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let mut responses = Vec::new();
+    for i in 0..3 {
+        let body = format!("name=le%20guin&email=ursula{}%40gmail.com", i);
+        responses.push(app.post_subscriptions(body).await);
+    }
+
+    // Assert
+    assert_eq!(responses[0].status().as_u16(), 200);
+    assert_eq!(responses[1].status().as_u16(), 200);
+    assert_eq!(responses[2].status().as_u16(), 429);
+    let retry_after: u64 = responses[2]
+        .headers()
+        .get("Retry-After")
+        .expect("A 429 must say when to retry")
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(retry_after > 0 && retry_after <= 3600);
+}
+
+#[tokio::test]
+async fn sign_ups_for_the_same_email_address_are_limited() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.rate_limits.per_email.max_requests = 1;
+    })
+    .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let first = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    let second = app
+        .post_subscriptions("name=le%20guin&email=URSULA_LE_GUIN%40gmail.com".into())
+        .await;
+    let other_address = app
+        .post_subscriptions("name=le%20guin&email=someone_else%40gmail.com".into())
+        .await;
+
+    // Assert
+    assert_eq!(first.status().as_u16(), 200);
+    assert_eq!(second.status().as_u16(), 429);
+    assert!(second.headers().get("Retry-After").is_some());
+    assert_eq!(other_address.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn the_limits_hold_across_instances_sharing_a_database() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.rate_limits.per_email.max_requests = 1;
+    })
+    .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    app.post_subscriptions(body.into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Act - a second instance, started on the same database
+    let mut configuration = app.configuration.clone();
+    configuration.application.port = 0;
+    let other_instance = zero2prod::startup::Application::build(configuration)
+        .await
+        .unwrap();
+    let other_address = format!("http://127.0.0.1:{}", other_instance.port());
+    tokio::spawn(other_instance.run_until_stopped());
+    let response = reqwest::Client::new()
+        .post(format!("{}/subscriptions", other_address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body(body)
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 429);
+}
@@ -0,0 +1,154 @@
+use crate::helpers::{spawn_app, spawn_app_with};
+use crate::newsletters::create_confirmed_subscriber;
+use std::sync::Arc;
+use std::time::Duration;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use zero2prod::issue_delivery_worker::run_worker_until_stopped;
+use zero2prod::metrics::Metrics;
+use zero2prod::shutdown::Shutdown;
+
+/// Wait until the email server has received `n` requests in total.
+async fn wait_for_email_requests(email_server: &MockServer, n: usize) {
+    for _ in 0..100 {
+        if email_server.received_requests().await.unwrap().len() >= n {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    panic!("The email server never received {} requests.", n);
+}
+
+#[tokio::test]
+async fn in_flight_requests_complete_before_the_application_stops() {
+    // Arrange
+    let mut app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(1)))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let sign_up = {
+        let client = app.api_client.clone();
+        let url = format!("{}/subscriptions", &app.address);
+        tokio::spawn(async move {
+            client
+                .post(url)
+                .header("Content-Type", "application/x-www-form-urlencoded")
+                .body("name=le%20guin&email=ursula_le_guin%40gmail.com")
+                .send()
+                .await
+        })
+    };
+    // The sign-up is now waiting on the email provider.
+    wait_for_email_requests(&app.email_server, 1).await;
+
+    // Act
+    app.shutdown.trigger();
+
+    // Assert
+    let response = sign_up.await.unwrap().expect("The request was cut off.");
+    assert_eq!(response.status().as_u16(), 200);
+    tokio::time::timeout(Duration::from_secs(10), &mut app.server_task)
+        .await
+        .expect("The application did not stop.")
+        .unwrap()
+        .unwrap();
+    let outcome = reqwest::get(format!("{}/health/live", &app.address)).await;
+    assert!(outcome.is_err());
+}
+
+#[tokio::test]
+async fn readiness_fails_while_connections_are_still_accepted() {
+    // Arrange
+    let mut app = spawn_app_with(|c| c.application.shutdown_pre_stop_delay_seconds = 2).await;
+
+    // Act
+    app.shutdown.trigger();
+
+    // Assert
+    let response = reqwest::get(format!("{}/health/ready", &app.address))
+        .await
+        .expect("The connection was refused during the pre-stop delay.");
+    assert_eq!(response.status().as_u16(), 503);
+    let response = reqwest::get(format!("{}/health/live", &app.address))
+        .await
+        .expect("The connection was refused during the pre-stop delay.");
+    assert_eq!(response.status().as_u16(), 200);
+    tokio::time::timeout(Duration::from_secs(10), &mut app.server_task)
+        .await
+        .expect("The application did not stop after the pre-stop delay.")
+        .unwrap()
+        .unwrap();
+    let outcome = reqwest::get(format!("{}/health/live", &app.address)).await;
+    assert!(outcome.is_err());
+}
+
+#[tokio::test]
+async fn the_delivery_worker_finishes_its_current_task_before_stopping() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.post_newsletters(serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(1)))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let shutdown = Shutdown::new();
+    let worker = tokio::spawn(run_worker_until_stopped(
+        app.configuration.clone(),
+        Arc::new(Metrics::new().unwrap()),
+        shutdown.clone(),
+    ));
+    // The confirmation email, then the issue being delivered.
+    wait_for_email_requests(&app.email_server, 2).await;
+
+    // Act
+    shutdown.trigger();
+
+    // Assert
+    tokio::time::timeout(Duration::from_secs(10), worker)
+        .await
+        .expect("The worker did not stop.")
+        .unwrap()
+        .unwrap();
+    let n_queued = sqlx::query_scalar!("SELECT COUNT(*) FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_queued, Some(0));
+}
+
+#[tokio::test]
+async fn an_idle_delivery_worker_stops_straight_away() {
+    // Arrange
+    let app = spawn_app().await;
+    let shutdown = Shutdown::new();
+    let worker = tokio::spawn(run_worker_until_stopped(
+        app.configuration.clone(),
+        Arc::new(Metrics::new().unwrap()),
+        shutdown.clone(),
+    ));
+
+    // Act
+    shutdown.trigger();
+
+    // Assert
+    tokio::time::timeout(Duration::from_secs(2), worker)
+        .await
+        .expect("The worker did not stop.")
+        .unwrap()
+        .unwrap();
+}
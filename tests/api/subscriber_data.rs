@@ -0,0 +1,237 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use crate::newsletters::create_confirmed_subscriber;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::subscriber_links::LinkPurpose;
+
+const EMAIL: &str = "ursula_le_guin@gmail.com";
+
+async fn log_in(app: &TestApp) {
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password
+    }))
+    .await;
+}
+
+async fn get_admin_export(app: &TestApp, email: &str) -> reqwest::Response {
+    app.api_client
+        .get(format!("{}/admin/subscribers/export", &app.address))
+        .query(&[("email", email)])
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn post_admin_erase(app: &TestApp, email: &str) -> reqwest::Response {
+    app.api_client
+        .post(format!("{}/admin/subscribers/erase", &app.address))
+        .json(&serde_json::json!({ "email": email }))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+/// Confirm a subscriber and deliver one issue to them.
+async fn create_subscriber_with_history(app: &TestApp) {
+    create_confirmed_subscriber(app).await;
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_newsletters(serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    app.dispatch_all_pending_emails().await;
+}
+
+/// The signed links of the preference center, as served to our frontend.
+async fn get_preferences_json(app: &TestApp) -> serde_json::Value {
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let link = app.get_preferences_links(&email_request).html;
+    app.api_client
+        .get(link)
+        .header("Accept", "application/json")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_export_or_erase_subscriber_data() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let export_response = get_admin_export(&app, EMAIL).await;
+    let erase_response = post_admin_erase(&app, EMAIL).await;
+
+    // Assert
+    assert_is_redirect_to(&export_response, "/login");
+    assert_is_redirect_to(&erase_response, "/login");
+}
+
+#[tokio::test]
+async fn the_export_contains_everything_stored_about_a_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    create_subscriber_with_history(&app).await;
+    log_in(&app).await;
+
+    // Act
+    let response = get_admin_export(&app, EMAIL).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let export: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(export["subscriber"]["email"], EMAIL);
+    assert_eq!(export["subscriber"]["name"], "le guin");
+    assert_eq!(export["list_memberships"][0]["list"], "newsletter");
+    assert_eq!(export["subscription_tokens"].as_array().unwrap().len(), 1);
+    assert_eq!(export["deliveries"][0]["title"], "Newsletter title");
+    assert_eq!(export["deliveries"][0]["outcome"], "delivered");
+    assert!(export["pending_deliveries"].as_array().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn exporting_an_unknown_address_returns_a_404() {
+    // Arrange
+    let app = spawn_app().await;
+    log_in(&app).await;
+
+    // Act
+    let response = get_admin_export(&app, EMAIL).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn erasure_anonymizes_the_subscriber_and_keeps_counts_intact() {
+    // Arrange
+    let app = spawn_app().await;
+    create_subscriber_with_history(&app).await;
+    log_in(&app).await;
+
+    // Act
+    let response = post_admin_erase(&app, EMAIL).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT email, name, status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_ne!(saved.email, EMAIL);
+    assert_ne!(saved.name, "le guin");
+    assert_eq!(saved.status, "erased");
+    let tokens = sqlx::query!("SELECT subscription_token FROM subscription_tokens")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(tokens.is_empty());
+    let deliveries = sqlx::query!("SELECT outcome FROM issue_delivery_log")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(deliveries.len(), 1);
+    let memberships = sqlx::query!("SELECT status FROM list_memberships")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(memberships.len(), 1);
+
+    assert_eq!(get_admin_export(&app, EMAIL).await.status().as_u16(), 404);
+    assert_eq!(post_admin_erase(&app, EMAIL).await.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn subscribers_can_export_their_own_data_through_a_signed_link() {
+    // Arrange
+    let app = spawn_app().await;
+    create_subscriber_with_history(&app).await;
+    let preferences = get_preferences_json(&app).await;
+    let mut link = reqwest::Url::parse(preferences["export_link"].as_str().unwrap()).unwrap();
+    link.set_port(Some(app.port)).unwrap();
+
+    // Act
+    let response = reqwest::get(link.clone()).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .headers()
+        .get("Content-Disposition")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .starts_with("attachment"));
+    let export: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(export["subscriber"]["email"], EMAIL);
+
+    // A token issued for another purpose is rejected
+    let subscriber_id = export["subscriber"]["id"].as_str().unwrap();
+    let token = app
+        .subscriber_links
+        .token(LinkPurpose::Preferences, subscriber_id.parse().unwrap());
+    link.query_pairs_mut()
+        .clear()
+        .append_pair("subscriber_id", subscriber_id)
+        .append_pair("token", &token);
+    let response = reqwest::get(link).await.unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn subscribers_can_erase_their_own_data_from_the_preference_center() {
+    // Arrange
+    let app = spawn_app().await;
+    create_subscriber_with_history(&app).await;
+    let subscriber_id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id;
+    let token = app
+        .subscriber_links
+        .token(LinkPurpose::Erase, subscriber_id);
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/subscriptions/erase", &app.address))
+        .form(&[
+            ("subscriber_id", subscriber_id.to_string()),
+            ("token", token),
+        ])
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT email, status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_ne!(saved.email, EMAIL);
+    assert_eq!(saved.status, "erased");
+}
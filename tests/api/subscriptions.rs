@@ -1,4 +1,5 @@
 use crate::helpers::spawn_app;
+use crate::newsletters::{create_confirmed_subscriber, create_unconfirmed_subscriber};
 use wiremock::matchers::{method, path};
 use wiremock::{Mock, ResponseTemplate};
 
@@ -84,6 +85,225 @@ async fn subscribe_sends_a_confirmation_email_with_a_link() {
     assert_eq!(confirmation_links.html, confirmation_links.plain_text);
 }
 
+#[tokio::test]
+async fn the_confirmation_email_greets_the_subscriber_by_name() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=ursula%20%26%20co&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_subscriptions(body.into()).await;
+
+    // Assert
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert!(body["HtmlBody"]
+        .as_str()
+        .unwrap()
+        .contains("Hi ursula &amp; co,"));
+    assert!(body["TextBody"]
+        .as_str()
+        .unwrap()
+        .contains("Hi ursula & co,"));
+}
+
+#[tokio::test]
+async fn the_confirmation_email_is_written_in_the_language_of_the_browser() {
+    // Arrange
+    let app = spawn_app().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Accept-Language", "fr-CA, fr;q=0.9, en;q=0.8")
+        .form(&[("name", "le guin"), ("email", "ursula_le_guin@gmail.com")])
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["Subject"], "Bienvenue !");
+    assert!(body["TextBody"]
+        .as_str()
+        .unwrap()
+        .contains("Bienvenue dans notre newsletter"));
+    let saved = sqlx::query!("SELECT locale FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.locale, "fr");
+}
+
+#[tokio::test]
+async fn the_locale_chosen_in_the_form_beats_the_browser_preferences() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com&locale=en";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("Accept-Language", "fr")
+        .body(body)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["Subject"], "Welcome!");
+    let saved = sqlx::query!("SELECT locale FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.locale, "en");
+}
+
+#[tokio::test]
+async fn validation_errors_are_worded_in_the_language_of_the_browser() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Accept-Language", "fr")
+        .form(&[("name", "le guin"), ("email", "definitely-not-an-email")])
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(
+        response.text().await.unwrap(),
+        "definitely-not-an-email n'est pas une adresse e-mail valide."
+    );
+}
+
+#[tokio::test]
+async fn list_and_source_errors_are_worded_in_the_language_of_the_browser() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Accept-Language", "fr")
+        .form(&[
+            ("name", "le guin"),
+            ("email", "ursula_le_guin@gmail.com"),
+            ("lists", "Rust Weekly"),
+            ("source", " "),
+        ])
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(
+        response.text().await.unwrap(),
+        "Rust Weekly n'est pas un identifiant de liste valide.\n  \
+         n'est pas un identifiant de source valide."
+    );
+}
+
+#[tokio::test]
+async fn unknown_lists_are_reported_in_the_language_of_the_browser() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Accept-Language", "fr")
+        .form(&[
+            ("name", "le guin"),
+            ("email", "ursula_le_guin@gmail.com"),
+            ("lists", "no-such-list"),
+        ])
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(
+        response.text().await.unwrap(),
+        "Aucune liste ne s'appelle no-such-list."
+    );
+}
+
+#[tokio::test]
+async fn a_pending_subscriber_signing_up_again_switches_to_the_new_locale() {
+    // Arrange
+    let app = spawn_app().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com&locale=en".into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Act
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com&locale=fr".into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["Subject"], "Bienvenue !");
+    let saved = sqlx::query!("SELECT locale, status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.locale, "fr");
+    assert_eq!(saved.status, "pending_confirmation");
+}
+
 #[tokio::test]
 async fn subscribe_returns_a_400_when_data_is_missing() {
     // Arrange
@@ -133,6 +353,135 @@ async fn subscribe_returns_a_400_when_fields_are_present_but_invalid() {
     }
 }
 
+#[tokio::test]
+async fn subscribe_accepts_json_bodies() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_subscriptions_json(serde_json::json!({
+            "name": "le guin",
+            "email": "ursula_le_guin@gmail.com",
+            "lists": "newsletter"
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT email, name FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.email, "ursula_le_guin@gmail.com");
+    assert_eq!(saved.name, "le guin");
+}
+
+#[tokio::test]
+async fn json_sign_ups_get_every_invalid_field_back_at_once() {
+    // Arrange
+    let app = spawn_app().await;
+    let test_cases = vec![
+        (
+            serde_json::json!({"name": "a".repeat(257), "email": "not-an-email"}),
+            vec![("name", "too_long"), ("email", "invalid")],
+        ),
+        (
+            serde_json::json!({
+                "name": "<script>",
+                "email": "ursula_le_guin@gmail.com",
+                "lists": "Not A Slug"
+            }),
+            vec![("name", "forbidden_characters"), ("lists", "invalid")],
+        ),
+        (
+            serde_json::json!({}),
+            vec![("name", "blank"), ("email", "invalid")],
+        ),
+        (
+            serde_json::json!({
+                "name": format!("<{}>", "a".repeat(256)),
+                "email": "ursula_le_guin@gmail.com"
+            }),
+            vec![("name", "too_long"), ("name", "forbidden_characters")],
+        ),
+    ];
+
+    for (body, expected) in test_cases {
+        // Act
+        let response = app.post_subscriptions_json(body.clone()).await;
+
+        // Assert
+        assert_eq!(response.status().as_u16(), 400, "Payload: {}", body);
+        let errors: serde_json::Value = response.json().await.unwrap();
+        let errors: Vec<(&str, &str)> = errors["errors"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| (e["field"].as_str().unwrap(), e["code"].as_str().unwrap()))
+            .collect();
+        assert_eq!(errors, expected, "Payload: {}", body);
+    }
+    let saved = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(saved.is_empty());
+}
+
+#[tokio::test]
+async fn json_field_errors_are_worded_in_the_language_of_the_browser() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Accept-Language", "fr")
+        .json(&serde_json::json!({"name": " ", "email": "ursula_le_guin@gmail.com"}))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(
+        body,
+        serde_json::json!({"errors": [{
+            "field": "name",
+            "code": "blank",
+            "message": "Le nom d'abonné ne peut pas être vide."
+        }]})
+    );
+}
+
+#[tokio::test]
+async fn form_sign_ups_get_every_validation_message_in_plain_text() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_subscriptions("name=&email=definitely-not-an-email".into())
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(
+        response.text().await.unwrap(),
+        "The subscriber name cannot be blank.\n\
+         definitely-not-an-email is not a valid subscriber email"
+    );
+}
+
 #[tokio::test]
 async fn subscribe_fails_if_there_is_a_fatal_database_error() {
     // arrange
@@ -149,3 +498,260 @@ async fn subscribe_fails_if_there_is_a_fatal_database_error() {
     // assert
     assert_eq!(response.status().as_u16(), 500);
 }
+
+#[tokio::test]
+async fn subscribing_twice_while_pending_resends_the_same_confirmation_link() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    let first_links = create_unconfirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.post_subscriptions(body.into()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let second_links = app.get_confirmation_links(&email_request);
+    assert_eq!(first_links.html, second_links.html);
+
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscriptions.");
+    assert_eq!(saved.len(), 1);
+    assert_eq!(saved[0].status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn subscribing_again_after_confirming_sends_an_already_subscribed_notice() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.post_subscriptions(body.into()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let email: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(email["Subject"], "You are already subscribed");
+    let text_body = email["TextBody"].as_str().unwrap();
+    assert!(!text_body.contains("/subscriptions/confirm"));
+    app.get_preferences_links(&email_request);
+
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn the_already_subscribed_notice_uses_the_stored_name() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=someone%20else&email=ursula_le_guin%40gmail.com";
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_subscriptions(body.into()).await;
+
+    // Assert
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let email: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let text_body = email["TextBody"].as_str().unwrap();
+    assert!(text_body.contains("le guin"));
+    assert!(!text_body.contains("someone else"));
+}
+
+#[tokio::test]
+async fn subscribe_joins_the_default_list_when_none_is_requested() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_subscriptions(body.into()).await;
+
+    // Assert
+    let saved = sqlx::query!(
+        r#"
+        SELECT l.slug AS "slug!", m.status AS "status!"
+        FROM list_memberships m
+        JOIN lists l ON l.list_id = m.list_id
+        "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to fetch saved membership.");
+    assert_eq!(saved.slug, "newsletter");
+    assert_eq!(saved.status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn subscribe_can_target_several_lists() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_list("rust").await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com&lists=rust%2Cnewsletter";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.post_subscriptions(body.into()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!(
+        r#"
+        SELECT l.slug AS "slug!"
+        FROM list_memberships m
+        JOIN lists l ON l.list_id = m.list_id
+        ORDER BY l.slug
+        "#
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .expect("Failed to fetch saved memberships.");
+    let slugs: Vec<_> = saved.into_iter().map(|r| r.slug).collect();
+    assert_eq!(slugs, vec!["newsletter", "rust"]);
+}
+
+#[tokio::test]
+async fn subscribe_returns_a_400_for_unknown_lists() {
+    // Arrange
+    let app = spawn_app().await;
+    let test_cases = vec![
+        ("lists=does-not-exist", "a list that does not exist"),
+        ("lists=Not%20A%20Slug", "an invalid list identifier"),
+    ];
+
+    for (lists, description) in test_cases {
+        // Act
+        let body = format!("name=le%20guin&email=ursula_le_guin%40gmail.com&{}", lists);
+        let response = app.post_subscriptions(body).await;
+
+        // Assert
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not return a 400 Bad Request when the payload had {}.",
+            description
+        );
+    }
+    let saved = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(saved.is_empty());
+}
+
+#[tokio::test]
+async fn joining_another_list_requires_a_new_confirmation() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_list("rust").await;
+    create_confirmed_subscriber(&app).await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com&lists=rust";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.post_subscriptions(body.into()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let memberships = || async {
+        sqlx::query!(
+            r#"
+            SELECT l.slug AS "slug!", m.status AS "status!"
+            FROM list_memberships m
+            JOIN lists l ON l.list_id = m.list_id
+            ORDER BY l.slug
+            "#
+        )
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| (r.slug, r.status))
+        .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        memberships().await,
+        vec![
+            ("newsletter".to_string(), "confirmed".to_string()),
+            ("rust".to_string(), "pending_confirmation".to_string()),
+        ]
+    );
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let confirmation_links = app.get_confirmation_links(&email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    assert_eq!(
+        memberships().await,
+        vec![
+            ("newsletter".to_string(), "confirmed".to_string()),
+            ("rust".to_string(), "confirmed".to_string()),
+        ]
+    );
+}
@@ -0,0 +1,450 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use crate::newsletters::{create_confirmed_subscriber, create_unconfirmed_subscriber};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+/// Confirm a subscriber and return the preferences link from their
+/// confirmation email.
+async fn preferences_link(app: &TestApp) -> reqwest::Url {
+    create_confirmed_subscriber(app).await;
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    app.get_preferences_links(&email_request).html
+}
+
+fn query_parameter(link: &reqwest::Url, name: &str) -> String {
+    link.query_pairs()
+        .find(|(k, _)| k == name)
+        .unwrap()
+        .1
+        .into_owned()
+}
+
+/// Where a response redirects to, relative to the application address.
+fn location(response: &reqwest::Response) -> String {
+    response
+        .headers()
+        .get("Location")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_owned()
+}
+
+async fn post_preferences_form(app: &TestApp, form: &[(&str, &str)]) -> reqwest::Response {
+    app.api_client
+        .post(format!("{}/subscriptions/preferences", &app.address))
+        .form(form)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn get_memberships(app: &TestApp) -> Vec<(String, String)> {
+    sqlx::query!(
+        r#"
+        SELECT l.slug AS "slug!", m.status AS "status!"
+        FROM list_memberships m
+        JOIN lists l ON l.list_id = m.list_id
+        ORDER BY l.slug
+        "#
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| (r.slug, r.status))
+    .collect()
+}
+
+#[tokio::test]
+async fn newsletter_issues_contain_a_preferences_link() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_newsletters(serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let links = app.get_preferences_links(&email_request);
+    assert_eq!(links.html, links.plain_text);
+}
+
+#[tokio::test]
+async fn the_preferences_page_shows_the_current_preferences() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_list("rust").await;
+    let link = preferences_link(&app).await;
+
+    // Act
+    let response = reqwest::get(link).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains(&format!(
+        r#"name="name" value="{}""#,
+        htmlescape::encode_attribute("le guin")
+    )));
+    assert!(html_page.contains(r#"value="newsletter" checked>"#));
+    assert!(html_page.contains(r#"value="rust">"#));
+    assert!(html_page.contains(r#"<option value="immediate" selected>"#));
+    assert!(html_page.contains(r#"<form action="/subscriptions/unsubscribe" method="post">"#));
+}
+
+#[tokio::test]
+async fn the_preferences_page_is_available_as_json() {
+    // Arrange
+    let app = spawn_app().await;
+    let link = preferences_link(&app).await;
+
+    // Act
+    let response = app
+        .api_client
+        .get(link)
+        .header("Accept", "application/json")
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["name"], "le guin");
+    assert_eq!(body["digest_frequency"], "immediate");
+    assert_eq!(
+        body["lists"],
+        serde_json::json!([{"slug": "newsletter", "name": "Newsletter", "subscribed": true}])
+    );
+}
+
+#[tokio::test]
+async fn a_forged_preferences_token_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let mut link = preferences_link(&app).await;
+    let subscriber_id = query_parameter(&link, "subscriber_id");
+    let forged_token = "0".repeat(64);
+    link.query_pairs_mut()
+        .clear()
+        .append_pair("subscriber_id", &subscriber_id)
+        .append_pair("token", &forged_token);
+
+    // Act
+    let get_response = reqwest::get(link).await.unwrap();
+    let post_response = post_preferences_form(
+        &app,
+        &[
+            ("subscriber_id", &subscriber_id),
+            ("token", &forged_token),
+            ("name", "someone else"),
+            ("digest_frequency", "weekly"),
+        ],
+    )
+    .await;
+
+    // Assert
+    assert_eq!(get_response.status().as_u16(), 401);
+    assert_eq!(post_response.status().as_u16(), 401);
+    let saved = sqlx::query!("SELECT name FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.name, "le guin");
+}
+
+#[tokio::test]
+async fn submitting_the_preferences_form_saves_the_preferences() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_list("rust").await;
+    let link = preferences_link(&app).await;
+    let subscriber_id = query_parameter(&link, "subscriber_id");
+    let token = query_parameter(&link, "token");
+
+    // Act - Part 1 - Submit the form
+    let response = post_preferences_form(
+        &app,
+        &[
+            ("subscriber_id", &subscriber_id),
+            ("token", &token),
+            ("name", "Ursula K. Le Guin"),
+            ("digest_frequency", "weekly"),
+            ("lists", "rust"),
+        ],
+    )
+    .await;
+
+    // Assert
+    assert_is_redirect_to(
+        &response,
+        &format!("{}?{}", link.path(), link.query().unwrap()),
+    );
+    let saved = sqlx::query!("SELECT name, digest_frequency, status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.name, "Ursula K. Le Guin");
+    assert_eq!(saved.digest_frequency, "weekly");
+    assert_eq!(saved.status, "confirmed");
+    assert_eq!(
+        get_memberships(&app).await,
+        vec![
+            ("newsletter".to_string(), "unsubscribed".to_string()),
+            ("rust".to_string(), "confirmed".to_string()),
+        ]
+    );
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app
+        .api_client
+        .get(format!("{}{}", &app.address, location(&response)))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("<p><i>Your preferences have been updated.</i></p>"));
+}
+
+#[tokio::test]
+async fn an_invalid_name_is_reported_on_the_preferences_page() {
+    // Arrange
+    let app = spawn_app().await;
+    let link = preferences_link(&app).await;
+    let subscriber_id = query_parameter(&link, "subscriber_id");
+    let token = query_parameter(&link, "token");
+
+    // Act - Part 1 - Submit the form
+    let response = post_preferences_form(
+        &app,
+        &[
+            ("subscriber_id", &subscriber_id),
+            ("token", &token),
+            ("name", "<script>"),
+            ("digest_frequency", "daily"),
+            ("lists", "newsletter"),
+        ],
+    )
+    .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 303);
+    let saved = sqlx::query!("SELECT name, digest_frequency FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.name, "le guin");
+    assert_eq!(saved.digest_frequency, "immediate");
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app
+        .api_client
+        .get(format!("{}{}", &app.address, location(&response)))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("&lt;script&gt; is not a valid subscriber name."));
+}
+
+#[tokio::test]
+async fn preferences_can_be_updated_with_json() {
+    // Arrange
+    let app = spawn_app().await;
+    let link = preferences_link(&app).await;
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/subscriptions/preferences", &app.address))
+        .json(&serde_json::json!({
+            "subscriber_id": query_parameter(&link, "subscriber_id"),
+            "token": query_parameter(&link, "token"),
+            "name": "Ursula",
+            "digest_frequency": "daily",
+            "lists": ["newsletter"],
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["name"], "Ursula");
+    assert_eq!(body["digest_frequency"], "daily");
+    assert_eq!(body["lists"][0]["subscribed"], true);
+}
+
+#[tokio::test]
+async fn lists_joined_before_confirming_are_confirmed_with_the_subscription() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_list("rust").await;
+    let confirmation_links = create_unconfirmed_subscriber(&app).await;
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let link = app.get_preferences_links(&email_request).html;
+    let subscriber_id = query_parameter(&link, "subscriber_id");
+    let token = query_parameter(&link, "token");
+
+    // Act - Part 1 - Join another list
+    post_preferences_form(
+        &app,
+        &[
+            ("subscriber_id", &subscriber_id),
+            ("token", &token),
+            ("name", "le guin"),
+            ("digest_frequency", "immediate"),
+            ("lists", "newsletter"),
+            ("lists", "rust"),
+        ],
+    )
+    .await;
+
+    // Act - Part 2 - Confirm
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Act - Part 3 - Publish to the new list
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.post_newsletters(serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        },
+        "list": "rust",
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(
+        get_memberships(&app).await,
+        vec![
+            ("newsletter".to_string(), "confirmed".to_string()),
+            ("rust".to_string(), "confirmed".to_string()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn ticking_a_list_awaiting_confirmation_confirms_it() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_list("rust").await;
+    let link = preferences_link(&app).await;
+    // Someone signs the subscriber up to another list.
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com&lists=rust".into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Act
+    post_preferences_form(
+        &app,
+        &[
+            ("subscriber_id", &query_parameter(&link, "subscriber_id")),
+            ("token", &query_parameter(&link, "token")),
+            ("name", "le guin"),
+            ("digest_frequency", "immediate"),
+            ("lists", "newsletter"),
+            ("lists", "rust"),
+        ],
+    )
+    .await;
+
+    // Assert
+    assert_eq!(
+        get_memberships(&app).await,
+        vec![
+            ("newsletter".to_string(), "confirmed".to_string()),
+            ("rust".to_string(), "confirmed".to_string()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn leaving_every_list_unsubscribes() {
+    // Arrange
+    let app = spawn_app().await;
+    let link = preferences_link(&app).await;
+
+    // Act
+    post_preferences_form(
+        &app,
+        &[
+            ("subscriber_id", &query_parameter(&link, "subscriber_id")),
+            ("token", &query_parameter(&link, "token")),
+            ("name", "le guin"),
+            ("digest_frequency", "immediate"),
+        ],
+    )
+    .await;
+
+    // Assert
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "unsubscribed");
+    assert_eq!(
+        get_memberships(&app).await,
+        vec![("newsletter".to_string(), "unsubscribed".to_string())]
+    );
+}
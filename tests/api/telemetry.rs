@@ -0,0 +1,72 @@
+use crate::helpers::spawn_app;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+const PARENT_ID: &str = "00f067aa0ba902b7";
+
+/// The `traceparent` header of the only request sent to the email API.
+async fn outgoing_traceparent(email_server: &wiremock::MockServer) -> String {
+    let email_request = &email_server.received_requests().await.unwrap()[0];
+    email_request
+        .headers
+        .get(&"traceparent".into())
+        .expect("No trace context was sent to the email provider.")
+        .as_str()
+        .to_owned()
+}
+
+#[tokio::test]
+async fn a_sign_up_continues_the_trace_of_the_caller_up_to_the_email_provider() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("traceparent", format!("00-{}-{}-01", TRACE_ID, PARENT_ID))
+        .body("name=le%20guin&email=ursula_le_guin%40gmail.com")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let traceparent = outgoing_traceparent(&app.email_server).await;
+    let fields: Vec<&str> = traceparent.split('-').collect();
+    assert_eq!(fields.len(), 4);
+    assert_eq!(fields[1], TRACE_ID);
+    // The parent of the call is one of our spans, not the caller's.
+    assert_ne!(fields[2], PARENT_ID);
+    assert_eq!(fields[3], "01");
+}
+
+#[tokio::test]
+async fn a_sign_up_without_trace_context_starts_a_new_trace() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+
+    // Assert
+    let traceparent = outgoing_traceparent(&app.email_server).await;
+    let trace_id = traceparent.split('-').nth(1).unwrap();
+    assert_eq!(trace_id.len(), 32);
+    assert_ne!(trace_id, "0".repeat(32));
+}